@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use bloom_filter::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use bloom_filter::bloom_filters::bloom_filter_prod::BloomFilterProd;
+
+const ELEMENTS: u64 = 10_000_000;
+const FALSE_PROBABILITY: f32 = 0.01;
+/// Keys per request on the hot path that asked for batching.
+const BATCH: usize = 256;
+
+/// One key at a time against `contains_many`, at a size where every probe misses the cache.
+fn batch_lookups(c: &mut Criterion) {
+    let mut bl = BloomFilterProd::new(ELEMENTS as usize, FALSE_PROBABILITY);
+    let inserted = (0..ELEMENTS).map(u64::to_le_bytes).collect::<Vec<_>>();
+    bl.insert_many(&inserted);
+
+    // half hits, half misses, spread over the whole filter
+    let batches = (0..1000u64)
+        .map(|batch| {
+            (0..BATCH as u64)
+                .map(|i| (batch * 7919 + i * 104_729) % (2 * ELEMENTS))
+                .map(u64::to_le_bytes)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("contains_256");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("one_at_a_time", |b| {
+        let mut batches = batches.iter().cycle();
+        let mut results = [false; BATCH];
+        b.iter(|| {
+            let keys = batches.next().unwrap();
+            for (key, result) in keys.iter().zip(&mut results) {
+                *result = bl.contains(key);
+            }
+            black_box(&results);
+        });
+    });
+    group.bench_function("contains_many", |b| {
+        let mut batches = batches.iter().cycle();
+        let mut results = [false; BATCH];
+        b.iter(|| {
+            bl.contains_many(batches.next().unwrap(), &mut results);
+            black_box(&results);
+        });
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("insert_256");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("one_at_a_time", |b| {
+        let mut batches = batches.iter().cycle();
+        b.iter(|| {
+            batches
+                .next()
+                .unwrap()
+                .iter()
+                .for_each(|key| bl.insert(key))
+        });
+    });
+    group.bench_function("insert_many", |b| {
+        let mut batches = batches.iter().cycle();
+        b.iter(|| bl.insert_many(batches.next().unwrap()));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, batch_lookups);
+criterion_main!(benches);
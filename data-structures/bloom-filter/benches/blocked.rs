@@ -0,0 +1,41 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use bloom_filter::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use bloom_filter::bloom_filters::blocked_bloom_filter::BlockedBloomFilter;
+use bloom_filter::bloom_filters::bloom_filter_prod::BloomFilterProd;
+
+const ELEMENTS: u64 = 10_000_000;
+const FALSE_PROBABILITY: f32 = 0.01;
+
+/// Lookup latency at a size where neither filter fits in cache:
+/// `BloomFilterProd` probes 7 random cache lines, the blocked filter one.
+fn lookups(c: &mut Criterion) {
+    let mut prod = BloomFilterProd::new(ELEMENTS as usize, FALSE_PROBABILITY);
+    let mut blocked = BlockedBloomFilter::new(ELEMENTS as usize, FALSE_PROBABILITY);
+    for key in 0..ELEMENTS {
+        prod.insert(&key.to_le_bytes());
+        blocked.insert(&key.to_le_bytes());
+    }
+
+    let mut group = c.benchmark_group("lookup_10m");
+    group.bench_function("prod_hit", |b| {
+        let mut keys = (0..ELEMENTS).cycle();
+        b.iter(|| prod.contains(black_box(&keys.next().unwrap().to_le_bytes())));
+    });
+    group.bench_function("blocked_hit", |b| {
+        let mut keys = (0..ELEMENTS).cycle();
+        b.iter(|| blocked.contains(black_box(&keys.next().unwrap().to_le_bytes())));
+    });
+    group.bench_function("prod_miss", |b| {
+        let mut keys = ELEMENTS..;
+        b.iter(|| prod.contains(black_box(&keys.next().unwrap().to_le_bytes())));
+    });
+    group.bench_function("blocked_miss", |b| {
+        let mut keys = ELEMENTS..;
+        b.iter(|| blocked.contains(black_box(&keys.next().unwrap().to_le_bytes())));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lookups);
+criterion_main!(benches);
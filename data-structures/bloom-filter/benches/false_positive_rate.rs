@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use prettytable::{Row, Table};
+
+use bloom_filter::bloom_filter::BloomFilter;
+use bloom_filter::bloom_filters::atomic_bloom_filter::AtomicBloomFilter;
+use bloom_filter::bloom_filters::blocked_bloom_filter::BlockedBloomFilter;
+use bloom_filter::bloom_filters::bloom_filter_32_arr::BloomFilter32;
+use bloom_filter::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use bloom_filter::bloom_filters::counting_bloom_filter::CountingBloomFilter;
+use bloom_filter::bloom_filters::cuckoo_filter::CuckooFilter;
+use bloom_filter::bloom_filters::rotating_bloom_filter::RotatingBloomFilter;
+use bloom_filter::bloom_filters::scalable_bloom_filter::ScalableBloomFilter;
+use bloom_filter::false_positive_rate::measure;
+use bloom_filter::serialization::HashAlgorithm;
+
+const ELEMENTS: usize = 1_000_000;
+const PROBES: usize = 10_000_000;
+const TOLERANCE: f64 = 0.1;
+
+/// Large scale version of `tests/false_positive_rate.rs` that prints a table instead of
+/// timings: `cargo bench --bench false_positive_rate`.
+fn main() {
+    let mut table = Table::new();
+    table.add_row(Row::from(vec![
+        "filter", "target", "measured", "ok", "time",
+    ]));
+
+    for p in [0.01, 0.001] {
+        let seeded = &mut BloomFilterProd::with_hash_algorithm(ELEMENTS, p, HashAlgorithm::Seeded);
+        row(&mut table, "prod seeded", p, seeded);
+        let double = &mut BloomFilterProd::with_hash_algorithm(ELEMENTS, p, HashAlgorithm::Double);
+        row(&mut table, "prod double", p, double);
+        row(
+            &mut table,
+            "atomic",
+            p,
+            &mut AtomicBloomFilter::new(ELEMENTS, p),
+        );
+        row(
+            &mut table,
+            "blocked",
+            p,
+            &mut BlockedBloomFilter::new(ELEMENTS, p),
+        );
+        row(
+            &mut table,
+            "counting",
+            p,
+            &mut CountingBloomFilter::new(ELEMENTS, p),
+        );
+        row(&mut table, "cuckoo", p, &mut CuckooFilter::new(ELEMENTS, p));
+        row(
+            &mut table,
+            "scalable",
+            p,
+            &mut ScalableBloomFilter::new(ELEMENTS / 100, p),
+        );
+        let window = Duration::from_secs(3600);
+        let rotating = &mut RotatingBloomFilter::new(ELEMENTS, p, window, 4);
+        row(&mut table, "rotating", p, rotating);
+    }
+    table.printstd();
+
+    let mut table = Table::new();
+    table.add_row(Row::from(vec!["BloomFilter32 keys", "measured"]));
+    for elements in [1, 2, 4, 8, 16, 32] {
+        let report = measure(&mut BloomFilter32::default(), elements, PROBES / 10, 7);
+        table.add_row(Row::from(vec![
+            elements.to_string(),
+            format!("{:.4}", report.rate()),
+        ]));
+    }
+    table.printstd();
+}
+
+fn row(table: &mut Table, name: &str, false_probability: f32, filter: &mut impl BloomFilter) {
+    let start = Instant::now();
+    let report = measure(filter, ELEMENTS, PROBES, 42);
+
+    table.add_row(Row::from(vec![
+        name.to_string(),
+        false_probability.to_string(),
+        format!("{:.6}", report.rate()),
+        report
+            .is_within(false_probability as f64, TOLERANCE)
+            .to_string(),
+        format!("{:.1?}", start.elapsed()),
+    ]));
+}
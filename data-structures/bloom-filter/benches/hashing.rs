@@ -0,0 +1,47 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use bloom_filter::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use bloom_filter::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use bloom_filter::serialization::HashAlgorithm;
+
+const ELEMENTS: usize = 1_000_000;
+const FALSE_PROBABILITY: f32 = 0.01;
+
+fn keys(prefix: &str, count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("{prefix}-{i}")).collect()
+}
+
+/// Seeded hashing runs seahash `hash_count` (7 at 1%) times per key, double hashing twice.
+fn hashing(c: &mut Criterion) {
+    let inserted = keys("key", ELEMENTS);
+    let probes = keys("probe", 10_000);
+
+    let mut group = c.benchmark_group("hash_algorithm");
+    for hash_algorithm in [HashAlgorithm::Seeded, HashAlgorithm::Double] {
+        let name = format!("{hash_algorithm:?}");
+
+        group.bench_function(BenchmarkId::new("insert", &name), |b| {
+            let mut bl =
+                BloomFilterProd::with_hash_algorithm(ELEMENTS, FALSE_PROBABILITY, hash_algorithm);
+            let mut keys = inserted.iter().cycle();
+            b.iter(|| bl.insert(black_box(keys.next().unwrap())));
+        });
+
+        let mut bl =
+            BloomFilterProd::with_hash_algorithm(ELEMENTS, FALSE_PROBABILITY, hash_algorithm);
+        inserted.iter().for_each(|key| bl.insert(key));
+
+        group.bench_function(BenchmarkId::new("contains_hit", &name), |b| {
+            let mut keys = inserted.iter().cycle();
+            b.iter(|| bl.contains(black_box(keys.next().unwrap())));
+        });
+        group.bench_function(BenchmarkId::new("contains_miss", &name), |b| {
+            let mut keys = probes.iter().cycle();
+            b.iter(|| bl.contains(black_box(keys.next().unwrap())));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hashing);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use bloom_filter::server::{Config, Server};
+
+const USAGE: &str = "\
+usage:
+  bloom-server [--bind ADDR] [--snapshot FILE] [--snapshot-interval SECS]
+
+Serves RedisBloom's BF.ADD, BF.EXISTS, BF.MADD, BF.MEXISTS, BF.RESERVE and BF.INFO
+over RESP. ADDR defaults to 127.0.0.1:6379, snapshots to every 60 seconds.
+The filters in FILE are restored on start.";
+
+fn main() -> ExitCode {
+    let (addr, config) = match parse(std::env::args().skip(1)) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(reason) => {
+            eprintln!("bloom-server: {reason}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let server = match Server::bind(&addr, config) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("bloom-server: {addr}: {err}");
+            return ExitCode::from(3);
+        }
+    };
+    match server.local_addr() {
+        Ok(local) => println!("listening on {local}"),
+        Err(err) => eprintln!("bloom-server: {err}"),
+    }
+    server.run();
+    ExitCode::SUCCESS
+}
+
+/// `None` for `--help`.
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<(String, Config)>, String> {
+    let mut addr = "127.0.0.1:6379".to_string();
+    let mut config = Config::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+
+        match arg.as_str() {
+            "--bind" => addr = value()?,
+            "--snapshot" => config.snapshot_path = Some(PathBuf::from(value()?)),
+            "--snapshot-interval" => {
+                config.snapshot_interval = value()?
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .map(Duration::from_secs)
+                    .ok_or("--snapshot-interval must be a positive number of seconds")?
+            }
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("unexpected argument {other}")),
+        }
+    }
+    Ok(Some((addr, config)))
+}
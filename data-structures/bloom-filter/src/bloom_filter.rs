@@ -1,6 +1,86 @@
-pub trait BloomFilter {
-    fn insert(&mut self, key: &str);
-
+/// The query side of a filter. Static filters, built once from a fixed key set,
+/// only implement this half.
+pub trait ReadableBloomFilter {
     /// probably yes, definitely no.
-    fn contains(&self, key: &str) -> bool;
-}
\ No newline at end of file
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool;
+
+    /// `contains` for every key, `results[i]` for `keys[i]`. Filters override it when
+    /// looking at many keys at once is cheaper than one at a time.
+    ///
+    /// Panics unless there is one result per key.
+    fn contains_many<K: AsRef<[u8]>>(&self, keys: &[K], results: &mut [bool]) {
+        assert_eq!(keys.len(), results.len(), "one result per key");
+        for (key, result) in keys.iter().zip(results) {
+            *result = self.contains(key);
+        }
+    }
+
+    /// `contains_many` packed into bits, key `i` is bit `i % 64` of `mask[i / 64]`.
+    ///
+    /// Panics unless `mask` has exactly the words `keys` need.
+    fn contains_many_mask<K: AsRef<[u8]>>(&self, keys: &[K], mask: &mut [u64]) {
+        assert_eq!(mask.len(), keys.len().div_ceil(64), "one mask bit per key");
+        let mut results = [false; 64];
+        for (keys, word) in keys.chunks(64).zip(mask) {
+            let results = &mut results[..keys.len()];
+            self.contains_many(keys, results);
+            *word = results
+                .iter()
+                .rev()
+                .fold(0, |word, &found| word << 1 | found as u64);
+        }
+    }
+}
+
+/// Keys are hashed by their bytes, so `"mango"`, `String::from("mango")` and `b"mango"`
+/// all land on the same bits. Numeric keys go in as bytes too, e.g. `&id.to_le_bytes()`.
+pub trait BloomFilter: ReadableBloomFilter {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K);
+
+    fn insert_many<K: AsRef<[u8]>>(&mut self, keys: &[K]) {
+        keys.iter().for_each(|key| self.insert(key));
+    }
+}
+
+/// A bloom filter that can forget keys again.
+pub trait DeletableBloomFilter: BloomFilter {
+    /// Removes a previously inserted key.
+    /// Returns `false` (and leaves the filter untouched) if the key is definitely not present.
+    fn remove<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloom_filters::blocked_bloom_filter::BlockedBloomFilter;
+
+    #[test]
+    fn test_default_batch_methods() {
+        let mut bl = BlockedBloomFilter::new(1000, 0.01);
+        bl.insert_many(&["mango", "apple"]);
+
+        let mut results = [false; 3];
+        bl.contains_many(&["apple", "carrot", "mango"], &mut results);
+        assert_eq!(results, [true, false, true]);
+    }
+
+    #[test]
+    fn test_contains_many_mask() {
+        let mut bl = BlockedBloomFilter::new(1000, 0.01);
+        let keys = (0..100).map(|i| format!("key-{i}")).collect::<Vec<_>>();
+        keys.iter().step_by(3).for_each(|key| bl.insert(key));
+
+        let mut mask = [0; 2];
+        bl.contains_many_mask(&keys, &mut mask);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(mask[i / 64] >> (i % 64) & 1 == 1, bl.contains(key), "{key}");
+        }
+        assert_eq!(mask[1] >> 36, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "one result per key")]
+    fn test_contains_many_checks_lengths() {
+        BlockedBloomFilter::new(10, 0.01).contains_many(&["mango"], &mut []);
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::{BloomFilterProd, Indices};
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::HashAlgorithm;
+
+const WORD_BITS: usize = 64;
+
+/// Bloom filter that can be shared between threads without a lock.
+///
+/// Bits live in `AtomicU64` words and inserts set them with `fetch_or`, so `insert` and
+/// `contains` only need `&self`. Sizing and bit layout are the same as `BloomFilterProd`.
+///
+/// A `contains` racing with an `insert` of the same key may see only some of its bits and
+/// answer `false`; once `insert` has returned, every later `contains` sees the key.
+#[derive(Debug)]
+pub struct AtomicBloomFilter<H: KeyHasher = SeaHash> {
+    words: Vec<AtomicU64>,
+    bit_count: usize,
+    hash_count: usize,
+    hash_algorithm: HashAlgorithm,
+    hasher: H,
+}
+
+impl AtomicBloomFilter {
+    pub fn new(elements: usize, false_probability: f32) -> Self {
+        Self::with_hash_algorithm(elements, false_probability, HashAlgorithm::Seeded)
+    }
+
+    pub fn with_hash_algorithm(
+        elements: usize,
+        false_probability: f32,
+        hash_algorithm: HashAlgorithm,
+    ) -> Self {
+        Self::with_hasher(elements, false_probability, hash_algorithm, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> AtomicBloomFilter<H> {
+    pub fn with_hasher(
+        elements: usize,
+        false_probability: f32,
+        hash_algorithm: HashAlgorithm,
+        hasher: H,
+    ) -> Self {
+        assert!(
+            BloomFilterProd::supports(hash_algorithm),
+            "{hash_algorithm:?} does not derive bit indices"
+        );
+        let (bit_count, hash_count) =
+            BloomFilterProd::optimal_parameters(elements, false_probability);
+
+        Self {
+            words: (0..bit_count.div_ceil(WORD_BITS))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            bit_count,
+            hash_count,
+            hash_algorithm,
+            hasher,
+        }
+    }
+
+    pub fn insert<K: AsRef<[u8]> + ?Sized>(&self, key: &K) {
+        for index in self.indices(key.as_ref()) {
+            let (word, mask) = locate(index);
+            // Release pairs with the Acquire in `contains`
+            self.words[word].fetch_or(mask, Ordering::Release);
+        }
+    }
+
+    /// probably yes, definitely no.
+    pub fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.indices(key.as_ref()).all(|index| {
+            let (word, mask) = locate(index);
+            self.words[word].load(Ordering::Acquire) & mask != 0
+        })
+    }
+
+    fn indices<'a>(&self, key: &'a [u8]) -> Indices<'a, H> {
+        Indices::new(
+            key,
+            self.hasher.clone(),
+            self.hash_algorithm,
+            self.hash_count,
+            self.bit_count,
+        )
+    }
+}
+
+/// Word and bit mask of a bit index.
+fn locate(index: usize) -> (usize, u64) {
+    (index / WORD_BITS, 1 << (index % WORD_BITS))
+}
+
+impl<H: KeyHasher> BloomFilter for AtomicBloomFilter<H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        AtomicBloomFilter::insert(self, key)
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for AtomicBloomFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        AtomicBloomFilter::contains(self, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_init_zeros() {
+        let bl = AtomicBloomFilter::new(10, 0.01);
+        assert!(bl
+            .words
+            .iter()
+            .all(|word| word.load(Ordering::Relaxed) == 0));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AtomicBloomFilter>();
+    }
+
+    #[test]
+    fn test_same_layout_as_prod() {
+        let bl = AtomicBloomFilter::new(100, 0.01);
+        bl.insert("mango");
+
+        assert_eq!(
+            (bl.bit_count, bl.hash_count),
+            BloomFilterProd::optimal_parameters(100, 0.01)
+        );
+
+        let set = (0..bl.bit_count)
+            .filter(|&index| {
+                let (word, mask) = locate(index);
+                bl.words[word].load(Ordering::Relaxed) & mask != 0
+            })
+            .collect::<Vec<_>>();
+        let mut expected = bl.indices(b"mango").collect::<Vec<_>>();
+        expected.sort_unstable();
+        expected.dedup();
+        assert_eq!(set, expected);
+    }
+
+    #[test]
+    fn test_no_false_negatives_under_contention() {
+        const THREADS: usize = 8;
+        const KEYS_PER_THREAD: usize = 5_000;
+
+        let bl = Arc::new(AtomicBloomFilter::new(THREADS * KEYS_PER_THREAD, 0.01));
+
+        let handles = (0..THREADS)
+            .map(|thread| {
+                let bl = Arc::clone(&bl);
+                thread::spawn(move || {
+                    for i in 0..KEYS_PER_THREAD {
+                        let key = format!("thread-{thread}-key-{i}");
+                        bl.insert(&key);
+                        assert!(bl.contains(&key));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .for_each(|handle| handle.join().unwrap());
+
+        assert!((0..THREADS).all(|thread| {
+            (0..KEYS_PER_THREAD).all(|i| bl.contains(&format!("thread-{thread}-key-{i}")))
+        }));
+    }
+}
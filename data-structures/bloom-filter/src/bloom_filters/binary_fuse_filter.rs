@@ -0,0 +1,411 @@
+use std::io::{Read, Write};
+
+use crate::bloom_filter::ReadableBloomFilter;
+use crate::rng::SplitMix64;
+use crate::serialization::{
+    ChecksumReader, ChecksumWriter, HashAlgorithm, HashFunction, Header, SerializationError,
+};
+
+const MAGIC: [u8; 4] = *b"BFUS";
+const ARITY: usize = 3;
+const MAX_SEGMENT_LENGTH: usize = 1 << 18;
+const DEFAULT_MAX_ATTEMPTS: usize = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// Two keys hashed to the same 64-bit value, almost certainly the same key given twice.
+    DuplicateKeys,
+    /// Peeling failed with every seed tried.
+    TooManyAttempts { attempts: usize },
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateKeys => write!(f, "the key set contains duplicates"),
+            Self::TooManyAttempts { attempts } => {
+                write!(f, "construction failed after {attempts} seeds")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Configures and builds a `BinaryFuseFilter`.
+#[derive(Debug, Clone)]
+pub struct BinaryFuseFilterBuilder {
+    max_attempts: usize,
+    seed: u64,
+}
+
+impl Default for BinaryFuseFilterBuilder {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            seed: 0,
+        }
+    }
+}
+
+impl BinaryFuseFilterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many seeds to try before giving up. A seed fails with probability well under 1%.
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Seeds the generator the per-attempt hash seeds are drawn from.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn build<K: AsRef<[u8]>>(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<BinaryFuseFilter, BuildError> {
+        let mut hashes = keys
+            .into_iter()
+            .map(|key| BinaryFuseFilter::key_hash(key.as_ref()))
+            .collect::<Vec<_>>();
+
+        hashes.sort_unstable();
+        if hashes.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(BuildError::DuplicateKeys);
+        }
+
+        let mut filter = BinaryFuseFilter::with_capacity(hashes.len());
+        let mut rng = SplitMix64::new(self.seed);
+        for _ in 0..self.max_attempts {
+            filter.seed = rng.next_u64();
+            if filter.populate(&hashes) {
+                return Ok(filter);
+            }
+        }
+
+        Err(BuildError::TooManyAttempts {
+            attempts: self.max_attempts,
+        })
+    }
+}
+
+/// Binary fuse filter (Graf & Lemire) with 8-bit fingerprints: an immutable filter built
+/// from a known key set, about 9 bits per key at a 0.39% false positive rate.
+///
+/// Every key maps to three positions in consecutive segments of the fingerprint array;
+/// the xor of the three entries equals the key's fingerprint.
+#[derive(Debug, Clone)]
+pub struct BinaryFuseFilter {
+    seed: u64,
+    segment_length: usize,
+    segment_count: usize,
+    fingerprints: Vec<u8>,
+}
+
+impl BinaryFuseFilter {
+    /// Builds with the default settings, see `BinaryFuseFilterBuilder` for the knobs.
+    pub fn from_keys<K: AsRef<[u8]>>(
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<Self, BuildError> {
+        BinaryFuseFilterBuilder::new().build(keys)
+    }
+
+    /// Fingerprint array in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    /// Layout from the reference implementation for arity 3.
+    fn with_capacity(size: usize) -> Self {
+        let segment_length = if size == 0 {
+            4
+        } else {
+            let exponent = ((size as f64).ln() / 3.33f64.ln() + 2.25).floor() as u32;
+            (1usize << exponent).min(MAX_SEGMENT_LENGTH)
+        };
+
+        let size_factor = if size <= 1 {
+            0.0
+        } else {
+            f64::max(1.125, 0.875 + 0.25 * 1_000_000f64.ln() / (size as f64).ln())
+        };
+        let capacity = (size as f64 * size_factor).round() as usize;
+        let segment_count = capacity
+            .div_ceil(segment_length)
+            .saturating_sub(ARITY - 1)
+            .max(1);
+
+        Self {
+            seed: 0,
+            segment_length,
+            segment_count,
+            fingerprints: vec![0; (segment_count + ARITY - 1) * segment_length],
+        }
+    }
+
+    /// Always seahash: the filter is static, so there is no insert path to pick a hasher for,
+    /// and changing it would break the fuse filters already written.
+    fn key_hash(key: &[u8]) -> u64 {
+        seahash::hash(key)
+    }
+
+    /// murmur3 finalizer over the key hash and the filter's seed.
+    fn mix(&self, key_hash: u64) -> u64 {
+        let mut h = key_hash.wrapping_add(self.seed);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        h ^ (h >> 33)
+    }
+
+    fn fingerprint(hash: u64) -> u8 {
+        (hash ^ (hash >> 32)) as u8
+    }
+
+    /// One position in each of three consecutive segments.
+    fn positions(&self, hash: u64) -> [usize; ARITY] {
+        let segment_count_length = (self.segment_count * self.segment_length) as u128;
+        let first = ((hash as u128 * segment_count_length) >> 64) as usize;
+        let mask = self.segment_length - 1;
+
+        [
+            first,
+            (first + self.segment_length) ^ ((hash >> 18) as usize & mask),
+            (first + 2 * self.segment_length) ^ (hash as usize & mask),
+        ]
+    }
+
+    /// Peels the 3-hypergraph of the keys and assigns fingerprints in reverse peeling order.
+    /// Returns `false` if the graph has a core that can't be peeled with this seed.
+    fn populate(&mut self, key_hashes: &[u64]) -> bool {
+        let len = self.fingerprints.len();
+        let mut counts = vec![0u32; len];
+        let mut xor_hashes = vec![0u64; len];
+
+        for &key_hash in key_hashes {
+            let hash = self.mix(key_hash);
+            for position in self.positions(hash) {
+                counts[position] += 1;
+                xor_hashes[position] ^= hash;
+            }
+        }
+
+        let mut queue = (0..len)
+            .filter(|&position| counts[position] == 1)
+            .collect::<Vec<_>>();
+        let mut peeled = Vec::with_capacity(key_hashes.len());
+
+        while let Some(position) = queue.pop() {
+            if counts[position] != 1 {
+                continue;
+            }
+            // the only key left at this position
+            let hash = xor_hashes[position];
+            peeled.push((hash, position));
+
+            for other in self.positions(hash) {
+                counts[other] -= 1;
+                xor_hashes[other] ^= hash;
+                if counts[other] == 1 {
+                    queue.push(other);
+                }
+            }
+        }
+
+        if peeled.len() != key_hashes.len() {
+            return false;
+        }
+
+        self.fingerprints.fill(0);
+        for &(hash, position) in peeled.iter().rev() {
+            let others = self
+                .positions(hash)
+                .iter()
+                .filter(|&&other| other != position)
+                .fold(0, |acc, &other| acc ^ self.fingerprints[other]);
+            self.fingerprints[position] = Self::fingerprint(hash) ^ others;
+        }
+        true
+    }
+
+    /// Same framing as `BloomFilterProd::write_to`; the header's bit length covers the
+    /// fingerprint array, the body starts with the seed and segment layout.
+    pub fn write_to(&self, writer: impl Write) -> Result<(), SerializationError> {
+        let mut writer = ChecksumWriter::new(writer);
+
+        Header {
+            magic: MAGIC,
+            hash_algorithm: HashAlgorithm::Fuse,
+            hash_function: HashFunction::SeaHash,
+            hash_count: ARITY as u32,
+            bit_len: self.fingerprints.len() as u64 * 8,
+        }
+        .write(&mut writer)?;
+
+        writer.write_u64(self.seed)?;
+        writer.write_u64(self.segment_length as u64)?;
+        writer.write_u64(self.segment_count as u64)?;
+        writer.write_all(&self.fingerprints)?;
+
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn read_from(reader: impl Read) -> Result<Self, SerializationError> {
+        let mut reader = ChecksumReader::new(reader);
+        let header = Header::read(&mut reader, MAGIC)?;
+
+        if header.hash_function != HashFunction::SeaHash {
+            return Err(SerializationError::HashFunctionMismatch {
+                expected: HashFunction::SeaHash,
+                found: header.hash_function,
+            });
+        }
+        if header.hash_algorithm != HashAlgorithm::Fuse || header.hash_count != 3 {
+            return Err(SerializationError::InvalidParameters(
+                "not a 3-wise binary fuse filter",
+            ));
+        }
+
+        let seed = reader.read_u64()?;
+        let segment_length = reader.read_u64()? as usize;
+        let segment_count = reader.read_u64()? as usize;
+
+        let len = header.bit_len as usize / 8;
+        if !segment_length.is_power_of_two()
+            || segment_count
+                .checked_add(ARITY - 1)
+                .and_then(|n| n.checked_mul(segment_length))
+                != Some(len)
+        {
+            return Err(SerializationError::InvalidParameters(
+                "segment layout does not match the fingerprint array",
+            ));
+        }
+
+        // grows with the input so a lying header can't force a huge allocation
+        let mut fingerprints = Vec::new();
+        (&mut reader)
+            .take(len as u64)
+            .read_to_end(&mut fingerprints)?;
+        if fingerprints.len() != len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        reader.verify()?;
+
+        Ok(Self {
+            seed,
+            segment_length,
+            segment_count,
+            fingerprints,
+        })
+    }
+}
+
+impl ReadableBloomFilter for BinaryFuseFilter {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        let hash = self.mix(Self::key_hash(key.as_ref()));
+        let stored = self
+            .positions(hash)
+            .iter()
+            .fold(0, |acc, &position| acc ^ self.fingerprints[position]);
+
+        stored == Self::fingerprint(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("key-{i}")).collect()
+    }
+
+    #[test]
+    fn test_contains_every_key() {
+        let keys = keys(10_000);
+        let filter = BinaryFuseFilter::from_keys(&keys).unwrap();
+
+        assert!(keys.iter().all(|key| filter.contains(key)));
+    }
+
+    #[test]
+    fn test_small_key_sets() {
+        for count in [0, 1, 2, 3, 10] {
+            let keys = keys(count);
+            let filter = BinaryFuseFilter::from_keys(&keys).unwrap();
+            assert!(keys.iter().all(|key| filter.contains(key)), "{count} keys");
+        }
+    }
+
+    #[test]
+    fn test_bits_per_key_and_false_positive_rate() {
+        let filter = BinaryFuseFilter::from_keys(keys(100_000)).unwrap();
+
+        let bits_per_key = filter.size_in_bytes() as f64 * 8.0 / 100_000.0;
+        assert!(bits_per_key < 10.0, "{bits_per_key} bits per key");
+
+        let false_positives = (0..100_000)
+            .filter(|i| filter.contains(&format!("probe-{i}")))
+            .count();
+        assert!(false_positives < 500, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn test_duplicate_keys_rejected() {
+        assert_eq!(
+            BinaryFuseFilter::from_keys(["mango", "apple", "mango"]).unwrap_err(),
+            BuildError::DuplicateKeys
+        );
+    }
+
+    #[test]
+    fn test_out_of_attempts_reported() {
+        let builder = BinaryFuseFilterBuilder::new().max_attempts(0);
+        assert_eq!(
+            builder.build(keys(10)).unwrap_err(),
+            BuildError::TooManyAttempts { attempts: 0 }
+        );
+    }
+
+    #[test]
+    fn test_builder_seed_is_deterministic() {
+        let a = BinaryFuseFilterBuilder::new()
+            .seed(7)
+            .build(keys(1000))
+            .unwrap();
+        let b = BinaryFuseFilterBuilder::new()
+            .seed(7)
+            .build(keys(1000))
+            .unwrap();
+
+        assert_eq!(a.fingerprints, b.fingerprints);
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let keys = keys(1000);
+        let filter = BinaryFuseFilter::from_keys(&keys).unwrap();
+
+        let mut buf = Vec::new();
+        filter.write_to(&mut buf).unwrap();
+        let restored = BinaryFuseFilter::read_from(buf.as_slice()).unwrap();
+
+        assert_eq!(restored.fingerprints, filter.fingerprints);
+        assert!(keys.iter().all(|key| restored.contains(key)));
+
+        let last = buf.len() - 9;
+        buf[last] ^= 1;
+        assert!(matches!(
+            BinaryFuseFilter::read_from(buf.as_slice()),
+            Err(SerializationError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::rng::SplitMix64;
+
+const BLOCK_BITS: usize = 512;
+const WORD_BITS: usize = 64;
+const BLOCK_WORDS: usize = BLOCK_BITS / WORD_BITS;
+/// Bits of hash per position inside a block, and how many positions one `u64` yields.
+const POSITION_BITS: usize = BLOCK_BITS.trailing_zeros() as usize;
+const POSITIONS_PER_WORD: usize = 64 / POSITION_BITS;
+
+/// One cache line worth of bits.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(align(64))]
+struct Block([u64; BLOCK_WORDS]);
+
+/// Blocked bloom filter (Putze et al.): every key maps to a single 512-bit block and sets
+/// all its bits inside it, so a lookup touches one cache line instead of `hash_count`.
+///
+/// Keys are spread less evenly than over one big bit array, so for the same false positive
+/// rate a blocked filter needs a few percent more bits; `optimal_parameters` accounts for that.
+#[derive(Debug, Clone)]
+pub struct BlockedBloomFilter<H: KeyHasher = SeaHash> {
+    blocks: Vec<Block>,
+    hash_count: usize,
+    hasher: H,
+}
+
+impl BlockedBloomFilter {
+    pub fn new(elements: usize, false_probability: f32) -> Self {
+        Self::with_hasher(elements, false_probability, SeaHash)
+    }
+
+    /// Returns `(block_count, hash_count)`.
+    ///
+    /// Starts from the `BloomFilterProd` sizing and adds blocks until the expected false positive
+    /// rate of the blocked layout, `expected_false_positive_rate`, is back under the target.
+    pub fn optimal_parameters(elements: usize, false_probability: f32) -> (usize, usize) {
+        let (bit_count, hash_count) =
+            BloomFilterProd::optimal_parameters(elements, false_probability);
+        let mut block_count = bit_count.div_ceil(BLOCK_BITS).max(1);
+
+        while Self::expected_false_positive_rate(elements, block_count, hash_count)
+            > false_probability as f64
+        {
+            block_count += block_count.div_ceil(64); // grow ~1.5% per step
+        }
+
+        (block_count, hash_count)
+    }
+
+    /// The number of keys per block is Poisson distributed with mean `n / blocks`;
+    /// a block holding `i` keys behaves like a classic 512-bit filter with `i` keys.
+    pub fn expected_false_positive_rate(
+        elements: usize,
+        block_count: usize,
+        hash_count: usize,
+    ) -> f64 {
+        let mean = elements as f64 / block_count as f64;
+        let k = hash_count as f64;
+        let last = (mean + 12.0 * mean.sqrt() + 32.0).ceil() as usize;
+
+        let mut probability = (-mean).exp(); // P(0 keys in the block)
+        let mut rate = 0.0;
+        for keys in 0..=last {
+            if keys > 0 {
+                probability *= mean / keys as f64;
+            }
+            let fill = 1.0 - (1.0 - 1.0 / BLOCK_BITS as f64).powf(keys as f64 * k);
+            rate += probability * fill.powf(k);
+        }
+        rate
+    }
+}
+
+impl<H: KeyHasher> BlockedBloomFilter<H> {
+    pub fn with_hasher(elements: usize, false_probability: f32, hasher: H) -> Self {
+        let (block_count, hash_count) =
+            BlockedBloomFilter::optimal_parameters(elements, false_probability);
+
+        Self {
+            blocks: vec![Block::default(); block_count],
+            hash_count,
+            hasher,
+        }
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Block index from the first hash; the second seeds the bit positions inside it.
+    fn locate(&self, key: &[u8]) -> (usize, u64) {
+        let block_hash = self.hasher.hash(key, 0);
+        let bits_hash = self.hasher.hash(key, 1);
+
+        // multiply-shift maps the hash onto 0..block_count without a division
+        let block = ((block_hash as u128 * self.blocks.len() as u128) >> 64) as usize;
+        (block, bits_hash)
+    }
+}
+
+/// `(word, mask)` pairs of a key's bits in its block, 9 hash bits each, extended by `SplitMix64`.
+fn bit_positions(bits_hash: u64, hash_count: usize) -> impl Iterator<Item = (usize, u64)> {
+    let mut rng = SplitMix64::new(bits_hash);
+    let mut word = bits_hash;
+
+    (0..hash_count).map(move |i| {
+        if i > 0 && i % POSITIONS_PER_WORD == 0 {
+            word = rng.next_u64();
+        }
+        let bit = (word >> (i % POSITIONS_PER_WORD * POSITION_BITS)) as usize % BLOCK_BITS;
+        (bit / WORD_BITS, 1 << (bit % WORD_BITS))
+    })
+}
+
+impl<H: KeyHasher> BloomFilter for BlockedBloomFilter<H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        let (block, bits_hash) = self.locate(key.as_ref());
+        let words = &mut self.blocks[block].0;
+        for (word, mask) in bit_positions(bits_hash, self.hash_count) {
+            words[word] |= mask;
+        }
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for BlockedBloomFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        let (block, bits_hash) = self.locate(key.as_ref());
+        let words = &self.blocks[block].0;
+        bit_positions(bits_hash, self.hash_count).all(|(word, mask)| words[word] & mask != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_zeros() {
+        let bl = BlockedBloomFilter::new(1000, 0.01);
+        assert!(bl.blocks.iter().all(|block| block.0 == [0; BLOCK_WORDS]));
+    }
+
+    #[test]
+    fn test_blocks_are_cache_line_aligned() {
+        let bl = BlockedBloomFilter::new(1000, 0.01);
+        assert_eq!(std::mem::size_of::<Block>(), 64);
+        assert_eq!(bl.blocks.as_ptr() as usize % 64, 0);
+    }
+
+    #[test]
+    fn test_insert_touches_one_block() {
+        let mut bl = BlockedBloomFilter::new(1000, 0.01);
+        bl.insert("mango");
+
+        let touched = bl.blocks.iter().filter(|block| block.0 != [0; BLOCK_WORDS]);
+        assert_eq!(touched.count(), 1);
+        assert!(bl.contains("mango"));
+    }
+
+    #[test]
+    fn test_sized_larger_than_prod() {
+        let (bit_count, _) = BloomFilterProd::optimal_parameters(100_000, 0.01);
+        let (block_count, _) = BlockedBloomFilter::optimal_parameters(100_000, 0.01);
+
+        assert!(block_count * BLOCK_BITS > bit_count);
+        assert!(BlockedBloomFilter::expected_false_positive_rate(100_000, block_count, 7) <= 0.01);
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut bl = BlockedBloomFilter::new(10_000, 0.01);
+        (0..10_000).for_each(|i| bl.insert(&format!("key-{i}")));
+        assert!((0..10_000).all(|i| bl.contains(&format!("key-{i}"))));
+
+        let false_positives = (0..100_000)
+            .filter(|i| bl.contains(&format!("probe-{i}")))
+            .count();
+        assert!(false_positives < 1_250, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn test_false_positive_rate_with_many_hashes() {
+        // 10 hashes: positions sharing a block must not correlate between keys
+        let mut bl = BlockedBloomFilter::new(10_000, 0.001);
+        (0..10_000).for_each(|i| bl.insert(&format!("key-{i}")));
+
+        let false_positives = (0..200_000)
+            .filter(|i| bl.contains(&format!("probe-{i}")))
+            .count();
+        assert!(false_positives < 250, "{false_positives} false positives");
+    }
+}
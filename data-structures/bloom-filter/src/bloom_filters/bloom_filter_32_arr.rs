@@ -1,5 +1,10 @@
-use crate::bloom_filter::BloomFilter;
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
 
+/// 32 bits and two additive hashes, a teaching example rather than a usable filter.
+///
+/// Both hashes are the byte sum of the key, one offset by its length, so they behave like a
+/// single hash: `false_positive_rate::measure` finds about 3% false positives per key inserted,
+/// 12% at 4 keys and 50% at 16. `BloomFilterN` is the fixed-size filter to use instead.
 #[derive(Default)]
 pub struct BloomFilter32 {
     bits: [bool; 32],
@@ -17,28 +22,31 @@ impl std::fmt::Debug for BloomFilter32 {
 }
 
 impl BloomFilter32 {
-    fn additive_hasher(key: &str, seed: usize) -> usize {
-        key.chars().fold(0, |acc, ch| -> usize {
-            (acc + seed + (ch as usize % 32)) % 32 // modulo math return 0 - 31
+    fn additive_hasher(key: &[u8], seed: usize) -> usize {
+        key.iter().fold(0, |acc, &byte| -> usize {
+            (acc + seed + (byte as usize % 32)) % 32 // modulo math return 0 - 31
         })
     }
 }
 
-
 impl BloomFilter for BloomFilter32 {
-    fn insert(&mut self, key: &str) {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        let key = key.as_ref();
         let hash_a = Self::additive_hasher(key, 0);
         let hash_b = Self::additive_hasher(key, 1);
 
         self.bits[hash_a] = true;
         self.bits[hash_b] = true;
     }
-    
-    fn contains(&self, key: &str) -> bool {
+}
+
+impl ReadableBloomFilter for BloomFilter32 {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        let key = key.as_ref();
         let hash_a = Self::additive_hasher(key, 0);
         let hash_b = Self::additive_hasher(key, 1);
 
-        self.bits[hash_a] && self.bits[hash_b] 
+        self.bits[hash_a] && self.bits[hash_b]
     }
 }
 
@@ -55,14 +63,14 @@ mod tests {
 
     #[test]
     fn test_additive_hasher_empty_string() {
-        assert_eq!(BloomFilter32::additive_hasher("", 0), 0);
+        assert_eq!(BloomFilter32::additive_hasher(b"", 0), 0);
     }
 
     #[test]
     fn test_additive_hasher_seed_sensitive() {
         assert_ne!(
-            BloomFilter32::additive_hasher("ad", 0),
-            BloomFilter32::additive_hasher("ad", 1)
+            BloomFilter32::additive_hasher(b"ad", 0),
+            BloomFilter32::additive_hasher(b"ad", 1)
         );
     }
-}
\ No newline at end of file
+}
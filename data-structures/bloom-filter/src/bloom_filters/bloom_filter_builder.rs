@@ -0,0 +1,436 @@
+use std::f64::consts::LN_2;
+
+use bitvec::prelude::*;
+
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::{HashAlgorithm, MAX_HASH_COUNT};
+
+/// Longest `BitVec` there can be.
+const MAX_BITS: usize = BitSlice::<usize, Lsb0>::MAX_BITS;
+
+/// Why a `BloomFilterBuilder` can't derive its parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildError {
+    /// Exactly two of elements, false probability, bit count and hash count must be given.
+    ParameterCount(usize),
+    /// False probability and hash count only fix the bits per element, not how many.
+    NoSize,
+    ZeroElements,
+    ZeroBits,
+    ZeroHashes,
+    /// Must be strictly between 0 and 1.
+    FalseProbability(f64),
+    /// The bit budget can't hold a single element at the requested rate or hash count.
+    TooFewBits,
+    BitCountTooLarge(f64),
+    /// Above `serialization::MAX_HASH_COUNT`, such a filter could be written but never read.
+    HashCountTooLarge(usize),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParameterCount(given) => write!(
+                f,
+                "exactly two of elements, false probability, bit count and hash count are \
+                 needed, {given} given"
+            ),
+            Self::NoSize => write!(
+                f,
+                "false probability and hash count don't determine a size, give elements or \
+                 bit count instead of one of them"
+            ),
+            Self::ZeroElements => write!(f, "elements must be at least 1"),
+            Self::ZeroBits => write!(f, "bit count must be at least 1"),
+            Self::ZeroHashes => write!(f, "hash count must be at least 1"),
+            Self::FalseProbability(p) => {
+                write!(f, "false probability {p} is not between 0 and 1")
+            }
+            Self::TooFewBits => write!(f, "bit count too small to hold a single element"),
+            Self::BitCountTooLarge(bits) => write!(f, "{bits} bits can't be allocated"),
+            Self::HashCountTooLarge(hashes) => write!(f, "{hashes} hash functions are too many"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// What a `BloomFilterBuilder` derives; nothing is allocated until `build`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameters {
+    pub elements: usize,
+    /// Expected false positive rate once `elements` keys are inserted.
+    pub false_probability: f64,
+    pub bit_count: usize,
+    pub hash_count: usize,
+}
+
+impl Parameters {
+    /// Memory the bits take, as `BloomFilterProd` and the serialized format store them.
+    pub fn size_bytes(&self) -> usize {
+        self.bit_count.div_ceil(64) * 8
+    }
+
+    pub fn bits_per_element(&self) -> f64 {
+        self.bit_count as f64 / self.elements as f64
+    }
+}
+
+/// Sizes a `BloomFilterProd` from any two of expected elements, target false positive rate,
+/// bit budget and hash count, deriving the others in `f64`:
+///
+/// ```
+/// use bloom_filter::bloom_filters::bloom_filter_builder::BloomFilterBuilder;
+///
+/// let parameters = BloomFilterBuilder::new()
+///     .elements(1_000_000)
+///     .false_probability(0.001)
+///     .parameters()
+///     .unwrap();
+/// assert_eq!(parameters.hash_count, 10);
+/// assert!(parameters.size_bytes() < 2 * 1024 * 1024);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomFilterBuilder {
+    elements: Option<usize>,
+    false_probability: Option<f64>,
+    bit_count: Option<usize>,
+    hash_count: Option<usize>,
+    hash_algorithm: HashAlgorithm,
+}
+
+impl Default for BloomFilterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BloomFilterBuilder {
+    pub fn new() -> Self {
+        Self {
+            elements: None,
+            false_probability: None,
+            bit_count: None,
+            hash_count: None,
+            hash_algorithm: HashAlgorithm::Seeded,
+        }
+    }
+
+    pub fn elements(mut self, elements: usize) -> Self {
+        self.elements = Some(elements);
+        self
+    }
+
+    pub fn false_probability(mut self, false_probability: f64) -> Self {
+        self.false_probability = Some(false_probability);
+        self
+    }
+
+    pub fn bit_count(mut self, bit_count: usize) -> Self {
+        self.bit_count = Some(bit_count);
+        self
+    }
+
+    pub fn hash_count(mut self, hash_count: usize) -> Self {
+        self.hash_count = Some(hash_count);
+        self
+    }
+
+    /// `HashAlgorithm::Seeded` unless set.
+    pub fn hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// Derives the parameters the two given ones imply:
+    ///
+    /// - elements and rate: the fewest bits that reach the rate with a whole number of
+    ///   hashes, about `m = -n ln p / ln² 2`
+    /// - elements and bits, or rate and bits: the other of the two, at the optimal load
+    /// - elements and hash count: the bits that make the hash count optimal, `m = k n / ln 2`
+    /// - bits and hash count: the elements at which that hash count is optimal
+    ///
+    /// The hash count, where derived, is whichever integer next to `m/n ln 2` gives the lower
+    /// rate.
+    pub fn parameters(&self) -> Result<Parameters, BuildError> {
+        let given = [
+            self.elements.is_some(),
+            self.false_probability.is_some(),
+            self.bit_count.is_some(),
+            self.hash_count.is_some(),
+        ];
+        let given = given.iter().filter(|given| **given).count();
+        if given != 2 {
+            return Err(BuildError::ParameterCount(given));
+        }
+
+        if self.elements == Some(0) {
+            return Err(BuildError::ZeroElements);
+        }
+        if self.bit_count == Some(0) {
+            return Err(BuildError::ZeroBits);
+        }
+        if self.hash_count == Some(0) {
+            return Err(BuildError::ZeroHashes);
+        }
+        if let Some(p) = self.false_probability {
+            // also rejects NaN
+            if !(p > 0.0 && p < 1.0) {
+                return Err(BuildError::FalseProbability(p));
+            }
+        }
+
+        let (elements, bit_count, hash_count) = match (
+            self.elements,
+            self.false_probability,
+            self.bit_count,
+            self.hash_count,
+        ) {
+            (Some(n), Some(p), None, None) => {
+                let (m, k) = fewest_bits(n, p)?;
+                (n, m, k)
+            }
+            (Some(n), None, Some(m), None) => (n, m, best_hash_count(n, m)),
+            (Some(n), None, None, Some(k)) => (n, checked_bits(k as f64 * n as f64 / LN_2)?, k),
+            (None, Some(p), Some(m), None) => {
+                let n = checked_elements(m as f64 * LN_2 * LN_2 / -p.ln())?;
+                (n, m, best_hash_count(n, m))
+            }
+            (None, None, Some(m), Some(k)) => (checked_elements(m as f64 * LN_2 / k as f64)?, m, k),
+            (None, Some(_), None, Some(_)) => return Err(BuildError::NoSize),
+            _ => unreachable!("exactly two parameters are given"),
+        };
+
+        if hash_count > MAX_HASH_COUNT as usize {
+            return Err(BuildError::HashCountTooLarge(hash_count));
+        }
+
+        Ok(Parameters {
+            elements,
+            false_probability: false_positive_rate(elements, bit_count, hash_count),
+            bit_count,
+            hash_count,
+        })
+    }
+
+    pub fn build(&self) -> Result<BloomFilterProd, BuildError> {
+        self.build_with_hasher(SeaHash)
+    }
+
+    pub fn build_with_hasher<H: KeyHasher>(
+        &self,
+        hasher: H,
+    ) -> Result<BloomFilterProd<H>, BuildError> {
+        let parameters = self.parameters()?;
+        Ok(BloomFilterProd::with_parameters(
+            parameters.bit_count,
+            parameters.hash_count,
+            self.hash_algorithm,
+            hasher,
+        ))
+    }
+}
+
+/// `(m, k)` with the smallest `m` for which `n` elements stay at or below `p`. The optimal
+/// `k = -log2 p` is rarely whole, so the hash counts around it are tried, each with the `m`
+/// that solves `(1 - e^(-kn/m))^k = p`.
+fn fewest_bits(elements: usize, false_probability: f64) -> Result<(usize, usize), BuildError> {
+    let optimum = -false_probability.log2();
+    let lowest = (optimum.floor() as usize).saturating_sub(1).max(1);
+    let highest = optimum.ceil() as usize + 1;
+
+    let mut fewest = None;
+    for k in lowest..=highest {
+        // share of bits still clear at the target rate, 0 once p^(1/k) rounds to 1
+        let clear = 1.0 - false_probability.powf(1.0 / k as f64);
+        if clear <= 0.0 {
+            continue;
+        }
+        let m = checked_bits(-(k as f64) * elements as f64 / clear.ln())?;
+        if fewest.is_none_or(|(fewest, _)| m < fewest) {
+            fewest = Some((m, k));
+        }
+    }
+    Ok(fewest.expect("one hash function always works for p < 1"))
+}
+
+fn checked_bits(bits: f64) -> Result<usize, BuildError> {
+    let bits = bits.ceil();
+    if bits > MAX_BITS as f64 {
+        return Err(BuildError::BitCountTooLarge(bits));
+    }
+    Ok((bits as usize).max(1))
+}
+
+fn checked_elements(elements: f64) -> Result<usize, BuildError> {
+    match elements.floor() {
+        elements if elements < 1.0 => Err(BuildError::TooFewBits),
+        elements => Ok(elements as usize),
+    }
+}
+
+/// `(1 - e^(-kn/m))^k`
+fn false_positive_rate(elements: usize, bit_count: usize, hash_count: usize) -> f64 {
+    let k = hash_count as f64;
+    (1.0 - (-k * elements as f64 / bit_count as f64).exp()).powf(k)
+}
+
+fn best_hash_count(elements: usize, bit_count: usize) -> usize {
+    let optimum = bit_count as f64 / elements as f64 * LN_2;
+    let below = (optimum.floor() as usize).max(1);
+    let above = (optimum.ceil() as usize).max(1);
+
+    if false_positive_rate(elements, bit_count, above)
+        < false_positive_rate(elements, bit_count, below)
+    {
+        above
+    } else {
+        below
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+
+    #[test]
+    fn test_elements_and_rate() {
+        let parameters = BloomFilterBuilder::new()
+            .elements(10_000)
+            .false_probability(0.01)
+            .parameters()
+            .unwrap();
+
+        assert_eq!(parameters.bit_count, 95_930);
+        assert_eq!(parameters.hash_count, 7);
+        assert!(parameters.false_probability <= 0.01);
+        assert_eq!(parameters.size_bytes(), 11_992);
+    }
+
+    #[test]
+    fn test_precision_for_large_filters() {
+        // f32 has 24 bits of mantissa, too few to size this to the bit
+        let parameters = BloomFilterBuilder::new()
+            .elements(1_000_000_000)
+            .false_probability(0.01)
+            .parameters()
+            .unwrap();
+
+        let (n, m, k) = (1_000_000_000, parameters.bit_count, parameters.hash_count);
+        assert!(false_positive_rate(n, m, k) <= 0.01);
+        assert!(false_positive_rate(n, m - 1, k) > 0.01);
+    }
+
+    #[test]
+    fn test_every_pair_round_trips() {
+        let target = BloomFilterBuilder::new()
+            .elements(1000)
+            .false_probability(0.01)
+            .parameters()
+            .unwrap();
+
+        let from_bits =
+            |builder: BloomFilterBuilder| builder.bit_count(target.bit_count).parameters().unwrap();
+        assert_eq!(
+            from_bits(BloomFilterBuilder::new().elements(1000)).hash_count,
+            7
+        );
+        assert_eq!(
+            from_bits(BloomFilterBuilder::new().false_probability(0.01)).elements,
+            1000
+        );
+        assert_eq!(
+            from_bits(BloomFilterBuilder::new().hash_count(7)).elements,
+            949
+        );
+
+        let from_hashes = BloomFilterBuilder::new()
+            .elements(1000)
+            .hash_count(7)
+            .parameters()
+            .unwrap();
+        assert!(from_hashes.false_probability < 0.01);
+        assert_eq!(from_hashes.bit_count, 10_099);
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        let builder = BloomFilterBuilder::new();
+        let cases = [
+            (builder, BuildError::ParameterCount(0)),
+            (builder.elements(10), BuildError::ParameterCount(1)),
+            (
+                builder.elements(10).false_probability(0.1).hash_count(3),
+                BuildError::ParameterCount(3),
+            ),
+            (
+                builder.false_probability(0.1).hash_count(3),
+                BuildError::NoSize,
+            ),
+            (
+                builder.elements(0).false_probability(0.1),
+                BuildError::ZeroElements,
+            ),
+            (builder.elements(10).bit_count(0), BuildError::ZeroBits),
+            (builder.elements(10).hash_count(0), BuildError::ZeroHashes),
+            (
+                builder.elements(10).false_probability(1.0),
+                BuildError::FalseProbability(1.0),
+            ),
+            (
+                builder.elements(10).false_probability(0.0),
+                BuildError::FalseProbability(0.0),
+            ),
+            (builder.bit_count(5).hash_count(10), BuildError::TooFewBits),
+            (
+                builder.bit_count(10).false_probability(1e-9),
+                BuildError::TooFewBits,
+            ),
+            (
+                builder.elements(usize::MAX).hash_count(usize::MAX),
+                BuildError::BitCountTooLarge((usize::MAX as f64).powi(2) / LN_2),
+            ),
+            (
+                builder.elements(1).bit_count(1 << 40),
+                BuildError::HashCountTooLarge(762_123_384_785),
+            ),
+            (
+                builder.elements(10).false_probability(1e-25),
+                BuildError::HashCountTooLarge(82),
+            ),
+            (
+                builder.elements(10).hash_count(65),
+                BuildError::HashCountTooLarge(65),
+            ),
+        ];
+
+        for (builder, expected) in cases {
+            assert_eq!(builder.parameters(), Err(expected), "{builder:?}");
+        }
+        assert!(matches!(
+            builder
+                .elements(10)
+                .false_probability(f64::NAN)
+                .parameters(),
+            Err(BuildError::FalseProbability(_))
+        ));
+    }
+
+    #[test]
+    fn test_build() {
+        let mut bl = BloomFilterBuilder::new()
+            .bit_count(1024)
+            .hash_count(3)
+            .hash_algorithm(HashAlgorithm::Double)
+            .build()
+            .unwrap();
+        bl.insert("mango");
+
+        assert!(bl.contains("mango"));
+        assert_eq!(bl.bit_count(), 1024);
+        assert_eq!(bl.hash_count(), 3);
+        assert_eq!(bl.hash_algorithm(), HashAlgorithm::Double);
+    }
+}
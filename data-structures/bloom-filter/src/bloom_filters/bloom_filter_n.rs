@@ -0,0 +1,183 @@
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::Indices;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::HashAlgorithm;
+
+/// Fixed-size filter of `WORDS` 64-bit words and `K` hashes, stored inline: no heap, so it can
+/// live in a struct, a static or on a small stack. Indices come from enhanced double hashing
+/// like `BloomFilterProd`'s `HashAlgorithm::Double`.
+///
+/// Sized in words rather than bits because stable Rust can't size an array by `BITS / 64`;
+/// a bit count works when the division is written out with a literal:
+///
+/// ```
+/// use bloom_filter::bloom_filter::{BloomFilter, ReadableBloomFilter};
+/// use bloom_filter::bloom_filters::bloom_filter_n::BloomFilterN;
+///
+/// // 8192 bits and 7 hashes, about 850 keys at 1%
+/// type Filter = BloomFilterN<{ 8192 / 64 }, 7>;
+///
+/// let mut bl = Filter::new();
+/// bl.insert("mango");
+/// assert!(bl.contains("mango"));
+/// assert_eq!(Filter::BITS, 8192);
+/// ```
+///
+/// `BloomFilterProd::builder()` works out the bits and hashes for an element count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilterN<const WORDS: usize, const K: usize, H: KeyHasher = SeaHash> {
+    words: [u64; WORDS],
+    hasher: H,
+}
+
+impl<const WORDS: usize, const K: usize> BloomFilterN<WORDS, K> {
+    pub const fn new() -> Self {
+        Self::with_hasher(SeaHash)
+    }
+}
+
+impl<const WORDS: usize, const K: usize> Default for BloomFilterN<WORDS, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const WORDS: usize, const K: usize, H: KeyHasher> BloomFilterN<WORDS, K, H> {
+    pub const BITS: usize = WORDS * 64;
+
+    pub const fn with_hasher(hasher: H) -> Self {
+        const {
+            assert!(WORDS > 0, "a filter needs at least one word");
+            assert!(K > 0, "a filter needs at least one hash");
+        }
+
+        Self {
+            words: [0; WORDS],
+            hasher,
+        }
+    }
+
+    /// Forgets every key.
+    pub fn clear(&mut self) {
+        self.words = [0; WORDS];
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    pub fn set_bits(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Fraction of bits set, about 0.5 when a filter reaches the element count it was sized for.
+    pub fn fill_ratio(&self) -> f64 {
+        self.set_bits() as f64 / Self::BITS as f64
+    }
+
+    /// False positive probability given the bits actually set, `fill_ratio ^ K`.
+    pub fn current_false_positive_rate(&self) -> f64 {
+        self.fill_ratio().powi(K as i32)
+    }
+
+    fn indices<'a>(&self, key: &'a [u8]) -> Indices<'a, H> {
+        Indices::new(
+            key,
+            self.hasher.clone(),
+            HashAlgorithm::Double,
+            K,
+            Self::BITS,
+        )
+    }
+}
+
+impl<const WORDS: usize, const K: usize, H: KeyHasher> BloomFilter for BloomFilterN<WORDS, K, H> {
+    fn insert<Key: AsRef<[u8]> + ?Sized>(&mut self, key: &Key) {
+        for index in self.indices(key.as_ref()) {
+            self.words[index / 64] |= 1 << (index % 64);
+        }
+    }
+}
+
+impl<const WORDS: usize, const K: usize, H: KeyHasher> ReadableBloomFilter
+    for BloomFilterN<WORDS, K, H>
+{
+    fn contains<Key: AsRef<[u8]> + ?Sized>(&self, key: &Key) -> bool {
+        self.indices(key.as_ref())
+            .all(|index| self.words[index / 64] & (1 << (index % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::SipHash24;
+
+    #[test]
+    fn test_init_zeros() {
+        let bl = BloomFilterN::<4, 3>::new();
+
+        assert_eq!(bl.set_bits(), 0);
+        assert!(!bl.contains("mango"));
+    }
+
+    #[test]
+    fn test_inline_storage() {
+        assert_eq!(size_of::<BloomFilterN<4, 3>>(), 32);
+        assert_eq!(size_of::<BloomFilterN<4, 3, SipHash24>>(), 48);
+
+        static FILTER: BloomFilterN<2, 2> = BloomFilterN::new();
+        assert!(!FILTER.contains("mango"));
+    }
+
+    #[test]
+    fn test_contains_inserted() {
+        let mut bl = BloomFilterN::<16, 7>::new();
+        let keys = (0..100u64).map(u64::to_le_bytes).collect::<Vec<_>>();
+        keys.iter().for_each(|key| bl.insert(key));
+
+        assert!(keys.iter().all(|key| bl.contains(key)));
+        assert!(bl.set_bits() <= 700);
+
+        bl.clear();
+        assert_eq!(bl.set_bits(), 0);
+    }
+
+    #[test]
+    fn test_anagrams_use_different_bits() {
+        let mut ab = BloomFilterN::<4, 3>::new();
+        let mut ba = BloomFilterN::<4, 3>::new();
+        ab.insert("ab");
+        ba.insert("ba");
+
+        assert_ne!(ab, ba);
+    }
+
+    #[test]
+    fn test_insert_sets_k_bits() {
+        let mut bl = BloomFilterN::<64, 5>::new();
+        bl.insert("mango");
+
+        // double hashing may revisit an index, rarely in 4096 bits
+        let set = bl.set_bits();
+        assert!((1..=5).contains(&set), "{set} bits set");
+        assert!(bl.contains("mango"));
+        assert_eq!(
+            bl.current_false_positive_rate(),
+            (set as f64 / 4096.0).powi(5)
+        );
+    }
+
+    #[test]
+    fn test_keyed_hashers_differ() {
+        let mut left = BloomFilterN::<4, 3, _>::with_hasher(SipHash24::new([1; 16]));
+        let mut right = BloomFilterN::<4, 3, _>::with_hasher(SipHash24::new([2; 16]));
+        left.insert("mango");
+        right.insert("mango");
+
+        assert_ne!(left.words, right.words);
+    }
+}
@@ -1,53 +1,491 @@
+use std::io::{Read, Write};
+
 use bitvec::prelude::*;
 
-use crate::bloom_filter::BloomFilter;
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_builder::BloomFilterBuilder;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::{
+    ChecksumReader, ChecksumWriter, HashAlgorithm, Header, SerializationError,
+};
+
+const MAGIC: [u8; 4] = *b"BLMF";
+/// Keys hashed ahead of the bit reads by the batch methods, enough to keep around a hundred
+/// cache misses in flight at the usual 7 hash functions.
+const BATCH: usize = 16;
+
+/// Returned by the set operations when two filters don't share their parameters.
+#[derive(Debug, PartialEq, Eq)]
+pub enum IncompatibleFilters {
+    BitLength {
+        left: usize,
+        right: usize,
+    },
+    HashCount {
+        left: usize,
+        right: usize,
+    },
+    HashAlgorithm {
+        left: HashAlgorithm,
+        right: HashAlgorithm,
+    },
+    /// Same hash function, different key, e.g. two `SipHash24` filters.
+    Hasher,
+}
+
+impl std::fmt::Display for IncompatibleFilters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BitLength { left, right } => {
+                write!(f, "bit lengths differ: {left} vs {right}")
+            }
+            Self::HashCount { left, right } => {
+                write!(f, "hash counts differ: {left} vs {right}")
+            }
+            Self::HashAlgorithm { left, right } => {
+                write!(f, "hash algorithms differ: {left:?} vs {right:?}")
+            }
+            Self::Hasher => write!(f, "hashers are keyed differently"),
+        }
+    }
+}
 
-#[derive(Debug)]
-pub struct BloomFilterProd {
+impl std::error::Error for IncompatibleFilters {}
+
+#[derive(Debug, Clone)]
+pub struct BloomFilterProd<H: KeyHasher = SeaHash> {
     bits: BitVec,
-    hash_count: usize,    
+    hash_count: usize,
+    hash_algorithm: HashAlgorithm,
+    hasher: H,
 }
 
 impl BloomFilterProd {
+    /// Panics if `elements` is 0, `false_probability` isn't between 0 and 1 or is so low it
+    /// needs more than `MAX_HASH_COUNT` hash functions, see `builder` for a fallible way and
+    /// more ways to size a filter.
     pub fn new(elements: usize, false_probability: f32) -> Self {
-        let log2 = 2f32.ln(); // log(2)
+        Self::with_hash_algorithm(elements, false_probability, HashAlgorithm::Seeded)
+    }
+
+    /// `HashAlgorithm::Double` hashes every key twice instead of `hash_count` times,
+    /// `Seeded` is kept for filters that were serialized with it.
+    pub fn with_hash_algorithm(
+        elements: usize,
+        false_probability: f32,
+        hash_algorithm: HashAlgorithm,
+    ) -> Self {
+        Self::with_hasher(elements, false_probability, hash_algorithm, SeaHash)
+    }
+
+    pub fn builder() -> BloomFilterBuilder {
+        BloomFilterBuilder::new()
+    }
+
+    /// Returns `(bit_count, hash_count)` for the given number of elements and false positive rate.
+    /// Panics where `new` does.
+    pub fn optimal_parameters(elements: usize, false_probability: f32) -> (usize, usize) {
+        let parameters = BloomFilterBuilder::new()
+            .elements(elements)
+            .false_probability(false_probability as f64)
+            .parameters()
+            .unwrap_or_else(|err| panic!("{err}"));
 
-        // m = -n * log2(p) / ln(2)
-        let bit_count = -((elements as f32 * false_probability.log2()) / log2).ceil() as usize;
-        // k = m/n * ln(2)
-        let hash_count = (bit_count as f32 / elements as f32 * log2).ceil() as usize;
+        (parameters.bit_count, parameters.hash_count)
+    }
+
+    /// Reads a seahash filter written by `write_to`, see `read_with_hasher`.
+    pub fn read_from(reader: impl Read) -> Result<Self, SerializationError> {
+        Self::read_with_hasher(reader, SeaHash)
+    }
+
+    /// Whether the algorithm spreads keys over a single bit array.
+    pub(crate) fn supports(hash_algorithm: HashAlgorithm) -> bool {
+        matches!(
+            hash_algorithm,
+            HashAlgorithm::Seeded | HashAlgorithm::Double
+        )
+    }
+}
+
+impl<H: KeyHasher> BloomFilterProd<H> {
+    pub fn with_hasher(
+        elements: usize,
+        false_probability: f32,
+        hash_algorithm: HashAlgorithm,
+        hasher: H,
+    ) -> Self {
+        let (bit_count, hash_count) =
+            BloomFilterProd::optimal_parameters(elements, false_probability);
+
+        Self::with_parameters(bit_count, hash_count, hash_algorithm, hasher)
+    }
+
+    pub(crate) fn with_parameters(
+        bit_count: usize,
+        hash_count: usize,
+        hash_algorithm: HashAlgorithm,
+        hasher: H,
+    ) -> Self {
+        assert!(
+            BloomFilterProd::supports(hash_algorithm),
+            "{hash_algorithm:?} does not derive bit indices"
+        );
 
         Self {
             bits: bitvec![0; bit_count],
             hash_count,
-        } 
+            hash_algorithm,
+            hasher,
+        }
+    }
+
+    /// Writes the filter in the versioned binary format described by `serialization::Header`,
+    /// followed by the bits packed into little endian `u64` words.
+    pub fn write_to(&self, writer: impl Write) -> Result<(), SerializationError> {
+        let mut writer = ChecksumWriter::new(writer);
+
+        Header {
+            magic: MAGIC,
+            hash_algorithm: self.hash_algorithm,
+            hash_function: H::FUNCTION,
+            hash_count: self.hash_count as u32,
+            bit_len: self.bits.len() as u64,
+        }
+        .write(&mut writer)?;
+
+        for word in self.bits.chunks(64) {
+            writer.write_u64(word.load_le::<u64>())?;
+        }
+
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Reads a filter written by `write_to`, rejecting corrupted or incompatible input
+    /// and filters built with another hash function than `hasher`'s.
+    pub fn read_with_hasher(reader: impl Read, hasher: H) -> Result<Self, SerializationError> {
+        let mut reader = ChecksumReader::new(reader);
+        let header = Header::read(&mut reader, MAGIC)?;
+        if header.hash_function != H::FUNCTION {
+            return Err(SerializationError::HashFunctionMismatch {
+                expected: H::FUNCTION,
+                found: header.hash_function,
+            });
+        }
+        if !BloomFilterProd::supports(header.hash_algorithm) {
+            return Err(SerializationError::InvalidParameters(
+                "hash algorithm does not derive bit indices",
+            ));
+        }
+
+        let bit_len = header.bit_len as usize;
+        // words are pushed as they arrive so a lying header can't force a huge allocation
+        let mut words = Vec::new();
+        for _ in 0..bit_len.div_ceil(64) {
+            words.push(reader.read_u64()?);
+        }
+        reader.verify()?;
+
+        let mut bits = bitvec![0; bit_len];
+        for (chunk, word) in bits.chunks_mut(64).zip(words) {
+            chunk.store_le(word);
+        }
+
+        Ok(Self {
+            bits,
+            hash_count: header.hash_count as usize,
+            hash_algorithm: header.hash_algorithm,
+            hasher,
+        })
+    }
+
+    /// Forgets every key, keeping the allocation and parameters.
+    pub fn clear(&mut self) {
+        self.bits.fill(false);
     }
 
-    fn hash(&self, key: &str, seed: usize) -> usize {
-        let hash = seahash::hash_seeded(key.as_bytes(), seed as u64, 0, 0, 0) as usize;
-        hash % self.bits.len() // get an index
+    pub fn bit_count(&self) -> usize {
+        self.bits.len()
     }
+
+    pub fn hash_count(&self) -> usize {
+        self.hash_count
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// The bits in index order, for the filters that share this layout.
+    pub(crate) fn bits(&self) -> &BitSlice {
+        &self.bits
+    }
+
+    pub fn set_bits(&self) -> usize {
+        self.bits.count_ones()
+    }
+
+    /// Fraction of bits set, about 0.5 when a filter reaches the element count it was sized for.
+    pub fn fill_ratio(&self) -> f64 {
+        self.set_bits() as f64 / self.bits.len() as f64
+    }
+
+    /// Estimated number of distinct keys inserted (Swamidass & Baldi): n = -m/k * ln(1 - X/m).
+    /// Infinite once every bit is set.
+    pub fn estimated_len(&self) -> f64 {
+        self.estimate_from_ones(self.set_bits())
+    }
+
+    /// False positive probability given the bits actually set, `fill_ratio ^ k`.
+    /// Compare with the `false_probability` the filter was built for to spot overfilled filters.
+    pub fn current_false_positive_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.hash_count as i32)
+    }
+
+    /// Filters can only be combined when they map every key to the same bits.
+    pub fn check_compatible(&self, other: &Self) -> Result<(), IncompatibleFilters> {
+        if self.bits.len() != other.bits.len() {
+            return Err(IncompatibleFilters::BitLength {
+                left: self.bits.len(),
+                right: other.bits.len(),
+            });
+        }
+        if self.hash_count != other.hash_count {
+            return Err(IncompatibleFilters::HashCount {
+                left: self.hash_count,
+                right: other.hash_count,
+            });
+        }
+        if self.hash_algorithm != other.hash_algorithm {
+            return Err(IncompatibleFilters::HashAlgorithm {
+                left: self.hash_algorithm,
+                right: other.hash_algorithm,
+            });
+        }
+        if self.hasher != other.hasher {
+            return Err(IncompatibleFilters::Hasher);
+        }
+        Ok(())
+    }
+
+    /// Adds every key of `other` to this filter. Exact: the result is what inserting both key sets gives.
+    pub fn union_with(&mut self, other: &Self) -> Result<(), IncompatibleFilters> {
+        self.check_compatible(other)?;
+        self.combine_words(other, |a, b| a | b);
+        Ok(())
+    }
+
+    /// Keeps only bits set in both filters. May report more false positives than
+    /// a filter built from the intersection of the key sets, never false negatives.
+    pub fn intersect_with(&mut self, other: &Self) -> Result<(), IncompatibleFilters> {
+        self.check_compatible(other)?;
+        self.combine_words(other, |a, b| a & b);
+        Ok(())
+    }
+
+    pub fn union(&self, other: &Self) -> Result<Self, IncompatibleFilters> {
+        let mut result = self.clone();
+        result.union_with(other)?;
+        Ok(result)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Result<Self, IncompatibleFilters> {
+        let mut result = self.clone();
+        result.intersect_with(other)?;
+        Ok(result)
+    }
+
+    /// Estimated Jaccard similarity `|A ∩ B| / |A ∪ B|` of the two key sets, in `[0, 1]`.
+    ///
+    /// Both sizes and the size of the union are estimated from the number of set bits,
+    /// the intersection follows by inclusion-exclusion.
+    pub fn similarity(&self, other: &Self) -> Result<f64, IncompatibleFilters> {
+        let union = self.union(other)?;
+
+        let union_size = union.estimated_len();
+        if union_size == 0.0 {
+            return Ok(1.0); // both empty
+        }
+
+        let intersection_size = self.estimated_len() + other.estimated_len() - union_size;
+
+        Ok((intersection_size / union_size).clamp(0.0, 1.0))
+    }
+
+    fn estimate_from_ones(&self, ones: usize) -> f64 {
+        let m = self.bits.len() as f64;
+        let k = self.hash_count as f64;
+
+        if ones == self.bits.len() {
+            return f64::INFINITY;
+        }
+        -(m / k) * (1.0 - ones as f64 / m).ln()
+    }
+
+    fn combine_words(&mut self, other: &Self, op: impl Fn(usize, usize) -> usize) {
+        self.bits
+            .as_raw_mut_slice()
+            .iter_mut()
+            .zip(other.bits.as_raw_slice())
+            .for_each(|(word, &other_word)| *word = op(*word, other_word));
+    }
+
+    /// Indices of `keys`, `hash_count` after another per key, prefetching the word of each.
+    fn prefetched_indices<K: AsRef<[u8]>>(&self, keys: &[K], indices: &mut Vec<usize>) {
+        indices.clear();
+        let words = self.bits.as_raw_slice();
+        for key in keys {
+            for index in self.indices(key.as_ref()) {
+                prefetch(&words[index / usize::BITS as usize]);
+                indices.push(index);
+            }
+        }
+    }
+
+    /// Owns everything it needs so bits can be set while iterating.
+    fn indices<'a>(&self, key: &'a [u8]) -> Indices<'a, H> {
+        Indices::new(
+            key,
+            self.hasher.clone(),
+            self.hash_algorithm,
+            self.hash_count,
+            self.bits.len(),
+        )
+    }
+}
+
+/// Asks the CPU to start loading `value`'s cache line, a no-op off x86_64.
+#[inline(always)]
+fn prefetch<T>(value: &T) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: a prefetch is only a hint and never faults, whatever the address
+    unsafe {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>(value as *const T as *const i8);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = value;
+}
+
+fn seeded_index(hasher: &impl KeyHasher, key: &[u8], seed: usize, len: usize) -> usize {
+    let hash = hasher.hash(key, seed as u64) as usize;
+    hash % len // get an index
 }
 
-impl BloomFilter for BloomFilterProd {
-    fn insert(&mut self, key: &str) {
-        for i in 0..self.hash_count {
-            let hash = self.hash(key, i);
-            self.bits.set(hash, true)
+/// Bit indices of one key, derived as the filter's `HashAlgorithm` prescribes.
+/// Shared with the other filters that lay their bits out like `BloomFilterProd`.
+pub(crate) struct Indices<'a, H> {
+    key: &'a [u8],
+    hasher: H,
+    hash_algorithm: HashAlgorithm,
+    hash_count: usize,
+    len: u64,
+    i: usize,
+    // enhanced double hashing: x walks h1, h1 + h2, h1 + 2*h2 + 1, ... (mod m)
+    x: u64,
+    y: u64,
+}
+
+impl<'a, H: KeyHasher> Indices<'a, H> {
+    pub(crate) fn new(
+        key: &'a [u8],
+        hasher: H,
+        hash_algorithm: HashAlgorithm,
+        hash_count: usize,
+        len: usize,
+    ) -> Self {
+        let len = len as u64;
+        let (x, y) = match hash_algorithm {
+            HashAlgorithm::Seeded | HashAlgorithm::Fuse => (0, 0),
+            HashAlgorithm::Double => (hasher.hash(key, 0) % len, hasher.hash(key, 1) % len),
+        };
+
+        Self {
+            key,
+            hasher,
+            hash_algorithm,
+            hash_count,
+            len,
+            i: 0,
+            x,
+            y,
         }
     }
+}
 
-    fn contains(&self, key: &str) -> bool {
-        (0..self.hash_count).all(|i| {
-            let hash = self.hash(key, i);
-            self.bits[hash]
-        })
+impl<H: KeyHasher> Iterator for Indices<'_, H> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.i == self.hash_count {
+            return None;
+        }
+
+        let index = match self.hash_algorithm {
+            HashAlgorithm::Seeded => {
+                seeded_index(&self.hasher, self.key, self.i, self.len as usize)
+            }
+            HashAlgorithm::Fuse => unreachable!("rejected by BloomFilterProd::supports"),
+            HashAlgorithm::Double => {
+                let index = self.x as usize;
+                self.x = (self.x + self.y) % self.len;
+                self.y = (self.y + self.i as u64 + 1) % self.len;
+                index
+            }
+        };
+
+        self.i += 1;
+        Some(index)
+    }
+}
+
+impl<H: KeyHasher> BloomFilter for BloomFilterProd<H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        for index in self.indices(key.as_ref()) {
+            self.bits.set(index, true)
+        }
+    }
+
+    /// Hashes `BATCH` keys and prefetches their words before setting any bit, so the
+    /// cache misses overlap instead of queuing up one key after another.
+    fn insert_many<K: AsRef<[u8]>>(&mut self, keys: &[K]) {
+        let mut indices = Vec::with_capacity(BATCH * self.hash_count);
+        for keys in keys.chunks(BATCH) {
+            self.prefetched_indices(keys, &mut indices);
+            indices.iter().for_each(|&index| self.bits.set(index, true));
+        }
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for BloomFilterProd<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.indices(key.as_ref()).all(|index| self.bits[index])
+    }
+
+    /// Prefetches like `insert_many`.
+    fn contains_many<K: AsRef<[u8]>>(&self, keys: &[K], results: &mut [bool]) {
+        assert_eq!(keys.len(), results.len(), "one result per key");
+        let mut indices = Vec::with_capacity(BATCH * self.hash_count);
+        for (keys, results) in keys.chunks(BATCH).zip(results.chunks_mut(BATCH)) {
+            self.prefetched_indices(keys, &mut indices);
+            let per_key = indices.chunks_exact(self.hash_count);
+            for (result, indices) in results.iter_mut().zip(per_key) {
+                *result = indices.iter().all(|&index| self.bits[index]);
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hashing::{DoubleSipHash24, Fnv1a, Murmur3, SipHash24};
+    use crate::serialization::HashFunction;
 
     #[test]
     fn test_init_zeros() {
@@ -58,16 +496,416 @@ mod tests {
     #[test]
     fn test_hash_order_sensitive() {
         let bl = BloomFilterProd::new(10, 0.01);
-        let hash1 = bl.hash("mango", 0);
-        let hash2 = bl.hash("mango", 1);
+        let hash1 = seeded_index(&SeaHash, b"mango", 0, bl.bits.len());
+        let hash2 = seeded_index(&SeaHash, b"mango", 1, bl.bits.len());
         assert_ne!(hash1, hash2);
     }
 
+    #[test]
+    fn test_serialization_round_trip() {
+        let mut bl = BloomFilterProd::new(100, 0.01);
+        ["mango", "apple", "orange", "banana"]
+            .iter()
+            .for_each(|key| bl.insert(key));
+
+        let mut buf = Vec::new();
+        bl.write_to(&mut buf).unwrap();
+        let restored = BloomFilterProd::read_from(buf.as_slice()).unwrap();
+
+        assert_eq!(restored.bits, bl.bits);
+        assert_eq!(restored.hash_count, bl.hash_count);
+        assert!(restored.contains("mango"));
+    }
+
+    #[test]
+    fn test_serialization_hash_count_limit() {
+        let mut bl = BloomFilterProd::with_parameters(1024, 64, HashAlgorithm::Double, SeaHash);
+        bl.insert("mango");
+        let mut buf = Vec::new();
+        bl.write_to(&mut buf).unwrap();
+        assert!(BloomFilterProd::read_from(buf.as_slice())
+            .unwrap()
+            .contains("mango"));
+
+        let bl = BloomFilterProd::with_parameters(1024, 65, HashAlgorithm::Double, SeaHash);
+        assert!(matches!(
+            bl.write_to(&mut Vec::new()),
+            Err(SerializationError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_rejects_corruption() {
+        let mut bl = BloomFilterProd::new(100, 0.01);
+        bl.insert("mango");
+        let mut buf = Vec::new();
+        bl.write_to(&mut buf).unwrap();
+
+        let mut flipped = buf.clone();
+        flipped[30] ^= 1;
+        assert!(matches!(
+            BloomFilterProd::read_from(flipped.as_slice()),
+            Err(SerializationError::ChecksumMismatch { .. })
+        ));
+
+        let mut wrong_magic = buf.clone();
+        wrong_magic[0] = b'X';
+        assert!(matches!(
+            BloomFilterProd::read_from(wrong_magic.as_slice()),
+            Err(SerializationError::BadMagic(_))
+        ));
+
+        let mut wrong_version = buf.clone();
+        wrong_version[4] = 99;
+        assert!(matches!(
+            BloomFilterProd::read_from(wrong_version.as_slice()),
+            Err(SerializationError::UnsupportedVersion(99))
+        ));
+
+        let mut wrong_hash = buf.clone();
+        wrong_hash[6] = 0;
+        assert!(matches!(
+            BloomFilterProd::read_from(wrong_hash.as_slice()),
+            Err(SerializationError::UnknownHashAlgorithm(0))
+        ));
+
+        let mut huge_hash_count = buf.clone();
+        huge_hash_count[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            BloomFilterProd::read_from(huge_hash_count.as_slice()),
+            Err(SerializationError::InvalidParameters(_))
+        ));
+
+        assert!(matches!(
+            BloomFilterProd::read_from(&buf[..buf.len() - 4]),
+            Err(SerializationError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_union() {
+        let mut a = BloomFilterProd::new(100, 0.01);
+        let mut b = BloomFilterProd::new(100, 0.01);
+        a.insert("mango");
+        b.insert("apple");
+
+        let union = a.union(&b).unwrap();
+        assert!(union.contains("mango"));
+        assert!(union.contains("apple"));
+
+        a.union_with(&b).unwrap();
+        assert_eq!(a.bits, union.bits);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let mut a = BloomFilterProd::new(100, 0.01);
+        let mut b = BloomFilterProd::new(100, 0.01);
+        a.insert("mango");
+        a.insert("apple");
+        b.insert("apple");
+        b.insert("orange");
+
+        let intersection = a.intersection(&b).unwrap();
+        assert!(intersection.contains("apple"));
+        assert!(!intersection.contains("mango"));
+        assert!(!intersection.contains("orange"));
+
+        a.intersect_with(&b).unwrap();
+        assert_eq!(a.bits, intersection.bits);
+    }
+
+    #[test]
+    fn test_set_operations_reject_mismatched_filters() {
+        let a = BloomFilterProd::new(100, 0.01);
+        let b = BloomFilterProd::new(1000, 0.01);
+
+        assert!(matches!(
+            a.union(&b),
+            Err(IncompatibleFilters::BitLength { .. })
+        ));
+        assert!(matches!(
+            a.intersection(&b),
+            Err(IncompatibleFilters::BitLength { .. })
+        ));
+        assert!(a.similarity(&b).is_err());
+
+        let mut c = a.clone();
+        c.hash_count += 1;
+        assert_eq!(
+            a.check_compatible(&c),
+            Err(IncompatibleFilters::HashCount {
+                left: a.hash_count,
+                right: a.hash_count + 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        let mut a = BloomFilterProd::new(10_000, 0.01);
+        let mut b = BloomFilterProd::new(10_000, 0.01);
+        // 1000 shared keys, 500 unique to each side: J = 1000 / 2000
+        (0..1500).for_each(|i| a.insert(&format!("key-{i}")));
+        (500..2000).for_each(|i| b.insert(&format!("key-{i}")));
+
+        let similarity = a.similarity(&b).unwrap();
+        assert!((similarity - 0.5).abs() < 0.05, "{similarity}");
+        assert!((a.similarity(&a).unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_key_types_share_bits() {
+        let mut from_str = BloomFilterProd::new(100, 0.01);
+        let mut from_string = BloomFilterProd::new(100, 0.01);
+        let mut from_bytes = BloomFilterProd::new(100, 0.01);
+        from_str.insert("mango");
+        from_string.insert(&String::from("mango"));
+        from_bytes.insert(b"mango");
+
+        assert_eq!(from_str.bits, from_string.bits);
+        assert_eq!(from_str.bits, from_bytes.bits);
+        assert!(from_bytes.contains("mango"));
+    }
+
+    #[test]
+    fn test_numeric_keys() {
+        let mut bl = BloomFilterProd::new(100, 0.01);
+        let user_id: u64 = 42;
+        bl.insert(&user_id.to_le_bytes());
+
+        assert!(bl.contains(&user_id.to_le_bytes()));
+        assert!(!bl.contains(&43u64.to_le_bytes()));
+    }
+
+    fn measured_false_positive_rate<H: KeyHasher>(
+        bl: &mut BloomFilterProd<H>,
+        elements: usize,
+    ) -> f64 {
+        (0..elements).for_each(|i| bl.insert(&format!("key-{i}")));
+        assert!((0..elements).all(|i| bl.contains(&format!("key-{i}"))));
+
+        let probes = 100_000;
+        let false_positives = (0..probes)
+            .filter(|i| bl.contains(&format!("probe-{i}")))
+            .count();
+        false_positives as f64 / probes as f64
+    }
+
+    /// p = (1 - e^(-kn/m))^k for the filter's actual m and k.
+    fn theoretical_false_positive_rate<H: KeyHasher>(
+        bl: &BloomFilterProd<H>,
+        elements: usize,
+    ) -> f64 {
+        let k = bl.hash_count as f64;
+        let exponent = -k * elements as f64 / bl.bits.len() as f64;
+        (1.0 - exponent.exp()).powf(k)
+    }
+
+    #[test]
+    fn test_false_positive_rate_within_bound() {
+        for hash_algorithm in [HashAlgorithm::Seeded, HashAlgorithm::Double] {
+            let mut bl = BloomFilterProd::with_hash_algorithm(10_000, 0.01, hash_algorithm);
+            let measured = measured_false_positive_rate(&mut bl, 10_000);
+            let theoretical = theoretical_false_positive_rate(&bl, 10_000);
+
+            assert!(
+                measured < theoretical * 1.25,
+                "{hash_algorithm:?}: measured {measured}, theoretical {theoretical}"
+            );
+        }
+    }
+
+    fn assert_false_positive_rate_within_bound<H: KeyHasher>(hasher: H) {
+        for hash_algorithm in [HashAlgorithm::Seeded, HashAlgorithm::Double] {
+            let mut bl = BloomFilterProd::with_hasher(10_000, 0.01, hash_algorithm, hasher.clone());
+            let measured = measured_false_positive_rate(&mut bl, 10_000);
+            let theoretical = theoretical_false_positive_rate(&bl, 10_000);
+
+            assert!(
+                measured < theoretical * 1.25,
+                "{hasher:?} {hash_algorithm:?}: measured {measured}, theoretical {theoretical}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_false_positive_rate_with_every_hasher() {
+        assert_false_positive_rate_within_bound(Fnv1a);
+        assert_false_positive_rate_within_bound(Murmur3);
+        assert_false_positive_rate_within_bound(SipHash24::new(*b"0123456789abcdef"));
+        assert_false_positive_rate_within_bound(DoubleSipHash24::new([7; 32]));
+    }
+
+    #[test]
+    fn test_hash_function_survives_serialization() {
+        let mut bl = BloomFilterProd::with_hasher(100, 0.01, HashAlgorithm::Double, Murmur3);
+        bl.insert("mango");
+
+        let mut buf = Vec::new();
+        bl.write_to(&mut buf).unwrap();
+        let restored = BloomFilterProd::read_with_hasher(buf.as_slice(), Murmur3).unwrap();
+        assert_eq!(restored.bits, bl.bits);
+        assert!(restored.contains("mango"));
+
+        assert!(matches!(
+            BloomFilterProd::read_from(buf.as_slice()),
+            Err(SerializationError::HashFunctionMismatch {
+                expected: HashFunction::SeaHash,
+                found: HashFunction::Murmur3,
+            })
+        ));
+        assert!(matches!(
+            BloomFilterProd::read_with_hasher(buf.as_slice(), Fnv1a),
+            Err(SerializationError::HashFunctionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_reads_version_1_as_seahash() {
+        let mut bl = BloomFilterProd::new(100, 0.01);
+        bl.insert("mango");
+
+        // version 1 had no hash function byte
+        let mut buf = Vec::new();
+        let mut writer = ChecksumWriter::new(&mut buf);
+        writer.write_all(&MAGIC).unwrap();
+        writer.write_all(&1u16.to_le_bytes()).unwrap();
+        writer.write_all(&[HashAlgorithm::Seeded.id()]).unwrap();
+        writer
+            .write_all(&(bl.hash_count as u32).to_le_bytes())
+            .unwrap();
+        writer.write_u64(bl.bits.len() as u64).unwrap();
+        for word in bl.bits.chunks(64) {
+            writer.write_u64(word.load_le::<u64>()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let restored = BloomFilterProd::read_from(buf.as_slice()).unwrap();
+        assert_eq!(restored.bits, bl.bits);
+        assert!(matches!(
+            BloomFilterProd::read_with_hasher(buf.as_slice(), Murmur3),
+            Err(SerializationError::HashFunctionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_set_operations_reject_differently_keyed_hashers() {
+        let a =
+            BloomFilterProd::with_hasher(100, 0.01, HashAlgorithm::Seeded, SipHash24::new([1; 16]));
+        let b =
+            BloomFilterProd::with_hasher(100, 0.01, HashAlgorithm::Seeded, SipHash24::new([2; 16]));
+
+        assert_eq!(a.check_compatible(&b), Err(IncompatibleFilters::Hasher));
+        assert!(a.check_compatible(&a.clone()).is_ok());
+    }
+
+    #[test]
+    fn test_double_hashing_indices() {
+        let bl = BloomFilterProd::with_hash_algorithm(10, 0.01, HashAlgorithm::Double);
+        let indices = bl.indices(b"mango").collect::<Vec<_>>();
+
+        assert_eq!(indices.len(), bl.hash_count);
+        assert!(indices.iter().all(|&index| index < bl.bits.len()));
+        assert_ne!(indices, bl.indices(b"ognam").collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_hash_algorithm_survives_serialization() {
+        let mut bl = BloomFilterProd::with_hash_algorithm(100, 0.01, HashAlgorithm::Double);
+        bl.insert("mango");
+
+        let mut buf = Vec::new();
+        bl.write_to(&mut buf).unwrap();
+        let restored = BloomFilterProd::read_from(buf.as_slice()).unwrap();
+
+        assert_eq!(restored.hash_algorithm, HashAlgorithm::Double);
+        assert!(restored.contains("mango"));
+    }
+
+    #[test]
+    fn test_set_operations_reject_mixed_hash_algorithms() {
+        let a = BloomFilterProd::new(100, 0.01);
+        let b = BloomFilterProd::with_hash_algorithm(100, 0.01, HashAlgorithm::Double);
+
+        assert!(matches!(
+            a.union(&b),
+            Err(IncompatibleFilters::HashAlgorithm { .. })
+        ));
+    }
+
+    #[test]
+    fn test_stats_of_empty_filter() {
+        let bl = BloomFilterProd::new(1000, 0.01);
+
+        assert_eq!(bl.set_bits(), 0);
+        assert_eq!(bl.fill_ratio(), 0.0);
+        assert_eq!(bl.estimated_len(), 0.0);
+        assert_eq!(bl.current_false_positive_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_estimated_len() {
+        let mut bl = BloomFilterProd::new(10_000, 0.01);
+        (0..5_000).for_each(|i| bl.insert(&format!("key-{i}")));
+
+        let estimate = bl.estimated_len();
+        assert!((estimate - 5_000.0).abs() < 150.0, "{estimate}");
+    }
+
+    #[test]
+    fn test_fill_tracks_design_point() {
+        let mut bl = BloomFilterProd::new(10_000, 0.01);
+        (0..10_000).for_each(|i| bl.insert(&format!("key-{i}")));
+
+        // an optimally sized filter is half full at capacity
+        assert!((bl.fill_ratio() - 0.5).abs() < 0.02, "{}", bl.fill_ratio());
+        assert!(bl.current_false_positive_rate() < 0.012);
+
+        (10_000..30_000).for_each(|i| bl.insert(&format!("key-{i}")));
+        assert!(bl.current_false_positive_rate() > 0.1);
+    }
+
+    #[test]
+    fn test_saturated_filter_estimate() {
+        let mut bl = BloomFilterProd::new(10, 0.5);
+        (0..1000).for_each(|i| bl.insert(&format!("key-{i}")));
+
+        assert_eq!(bl.fill_ratio(), 1.0);
+        assert_eq!(bl.estimated_len(), f64::INFINITY);
+        assert_eq!(bl.current_false_positive_rate(), 1.0);
+    }
+
     #[test]
     fn test_hash_palindrome_sensitive() {
         let bl = BloomFilterProd::new(10, 0.01);
-        let hash1 = bl.hash("mango", 0);
-        let hash2 = bl.hash("ognam", 0);
+        let hash1 = seeded_index(&SeaHash, b"mango", 0, bl.bits.len());
+        let hash2 = seeded_index(&SeaHash, b"ognam", 0, bl.bits.len());
         assert_ne!(hash1, hash2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_batches_match_single_key_calls() {
+        for hash_algorithm in [HashAlgorithm::Seeded, HashAlgorithm::Double] {
+            let keys = (0..1000).map(|i| format!("key-{i}")).collect::<Vec<_>>();
+            let mut batched = BloomFilterProd::with_hash_algorithm(500, 0.01, hash_algorithm);
+            let mut single = batched.clone();
+
+            batched.insert_many(&keys[..500]);
+            keys[..500].iter().for_each(|key| single.insert(key));
+            assert_eq!(batched.bits, single.bits);
+
+            let mut results = vec![false; keys.len()];
+            batched.contains_many(&keys, &mut results);
+            for (key, result) in keys.iter().zip(results) {
+                assert_eq!(result, single.contains(key), "{key}");
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "elements must be at least 1")]
+    fn test_new_rejects_zero_elements() {
+        BloomFilterProd::new(0, 0.01);
+    }
+}
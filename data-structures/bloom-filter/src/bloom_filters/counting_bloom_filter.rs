@@ -0,0 +1,251 @@
+use bitvec::prelude::*;
+
+use crate::bloom_filter::{BloomFilter, DeletableBloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::hashing::{KeyHasher, SeaHash};
+
+const DEFAULT_COUNTER_WIDTH: usize = 4;
+
+/// Returned by `try_insert` when one of the key's counters is already saturated.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CounterOverflow {
+    pub slot: usize,
+}
+
+impl std::fmt::Display for CounterOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "counter at slot {} is saturated", self.slot)
+    }
+}
+
+impl std::error::Error for CounterOverflow {}
+
+/// Bloom filter with a small saturating counter per slot instead of a single bit, so keys can be removed.
+///
+/// A counter that saturates is pinned at its maximum from then on: its real value is unknown,
+/// so decrementing it could introduce false negatives.
+#[derive(Debug)]
+pub struct CountingBloomFilter<H: KeyHasher = SeaHash> {
+    counters: BitVec,
+    counter_width: usize,
+    slots: usize,
+    hash_count: usize,
+    overflows: usize,
+    hasher: H,
+}
+
+impl CountingBloomFilter {
+    /// Sized like `BloomFilterProd::new`, with 4-bit counters.
+    pub fn new(elements: usize, false_probability: f32) -> Self {
+        Self::with_counter_width(elements, false_probability, DEFAULT_COUNTER_WIDTH)
+    }
+
+    /// `counter_width` is the number of bits per counter, between 1 and 8.
+    pub fn with_counter_width(
+        elements: usize,
+        false_probability: f32,
+        counter_width: usize,
+    ) -> Self {
+        Self::with_hasher(elements, false_probability, counter_width, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> CountingBloomFilter<H> {
+    pub fn with_hasher(
+        elements: usize,
+        false_probability: f32,
+        counter_width: usize,
+        hasher: H,
+    ) -> Self {
+        assert!(
+            (1..=8).contains(&counter_width),
+            "counter width must be between 1 and 8 bits"
+        );
+
+        let (slots, hash_count) = BloomFilterProd::optimal_parameters(elements, false_probability);
+
+        Self {
+            counters: bitvec![0; slots * counter_width],
+            counter_width,
+            slots,
+            hash_count,
+            overflows: 0,
+            hasher,
+        }
+    }
+
+    /// Inserts the key, failing without touching the filter if any of its counters is saturated.
+    pub fn try_insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> Result<(), CounterOverflow> {
+        let slots = self.slots_for(key.as_ref());
+
+        if let Some(&slot) = slots.iter().find(|&&slot| self.is_saturated(slot)) {
+            return Err(CounterOverflow { slot });
+        }
+
+        slots.into_iter().for_each(|slot| self.increment(slot));
+        Ok(())
+    }
+
+    /// Number of times an insert hit an already saturated counter.
+    pub fn overflows(&self) -> usize {
+        self.overflows
+    }
+
+    fn max_count(&self) -> u8 {
+        ((1u16 << self.counter_width) - 1) as u8
+    }
+
+    fn hash(&self, key: &[u8], seed: usize) -> usize {
+        let hash = self.hasher.hash(key, seed as u64) as usize;
+        hash % self.slots // get a slot index
+    }
+
+    /// Distinct slots for a key, so a slot hit by two hash functions is only counted once.
+    fn slots_for(&self, key: &[u8]) -> Vec<usize> {
+        let mut slots = (0..self.hash_count)
+            .map(|i| self.hash(key, i))
+            .collect::<Vec<_>>();
+        slots.sort_unstable();
+        slots.dedup();
+        slots
+    }
+
+    fn counter(&self, slot: usize) -> u8 {
+        let start = slot * self.counter_width;
+        self.counters[start..start + self.counter_width].load::<u8>()
+    }
+
+    fn set_counter(&mut self, slot: usize, value: u8) {
+        let start = slot * self.counter_width;
+        self.counters[start..start + self.counter_width].store::<u8>(value);
+    }
+
+    fn is_saturated(&self, slot: usize) -> bool {
+        self.counter(slot) == self.max_count()
+    }
+
+    fn increment(&mut self, slot: usize) {
+        let value = self.counter(slot);
+        self.set_counter(slot, value + 1);
+    }
+}
+
+impl<H: KeyHasher> BloomFilter for CountingBloomFilter<H> {
+    /// Saturated counters stay pinned; each hit on one is recorded in `overflows`.
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        for slot in self.slots_for(key.as_ref()) {
+            if self.is_saturated(slot) {
+                self.overflows += 1;
+            } else {
+                self.increment(slot);
+            }
+        }
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for CountingBloomFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        let key = key.as_ref();
+        (0..self.hash_count).all(|i| self.counter(self.hash(key, i)) > 0)
+    }
+}
+
+impl<H: KeyHasher> DeletableBloomFilter for CountingBloomFilter<H> {
+    fn remove<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> bool {
+        if !self.contains(key) {
+            return false;
+        }
+
+        for slot in self.slots_for(key.as_ref()) {
+            // saturated counters have lost their real value, leave them alone
+            if !self.is_saturated(slot) {
+                let value = self.counter(slot);
+                self.set_counter(slot, value - 1);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_zeros() {
+        let bl = CountingBloomFilter::new(10, 0.01);
+        (0..bl.slots).for_each(|slot| assert_eq!(bl.counter(slot), 0));
+    }
+
+    #[test]
+    fn test_same_sizing_as_prod() {
+        let bl = CountingBloomFilter::new(10, 0.01);
+        let (slots, hash_count) = BloomFilterProd::optimal_parameters(10, 0.01);
+
+        assert_eq!(bl.slots, slots);
+        assert_eq!(bl.hash_count, hash_count);
+        assert_eq!(bl.counters.len(), slots * DEFAULT_COUNTER_WIDTH);
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut bl = CountingBloomFilter::new(10, 0.01);
+        bl.insert("mango");
+        bl.insert("apple");
+
+        assert!(bl.remove("mango"));
+        assert!(!bl.contains("mango"));
+        assert!(bl.contains("apple"));
+    }
+
+    #[test]
+    fn test_remove_absent_key() {
+        let mut bl = CountingBloomFilter::new(10, 0.01);
+        bl.insert("apple");
+
+        assert!(!bl.remove("mango"));
+        assert!(bl.contains("apple"));
+    }
+
+    #[test]
+    fn test_duplicate_inserts_need_matching_removes() {
+        let mut bl = CountingBloomFilter::new(10, 0.01);
+        bl.insert("mango");
+        bl.insert("mango");
+
+        assert!(bl.remove("mango"));
+        assert!(bl.contains("mango"));
+        assert!(bl.remove("mango"));
+        assert!(!bl.contains("mango"));
+    }
+
+    #[test]
+    fn test_other_hasher() {
+        let mut bl = CountingBloomFilter::with_hasher(10, 0.01, 4, crate::hashing::Murmur3);
+        bl.insert("mango");
+
+        assert!(bl.contains("mango"));
+        assert!(bl.remove("mango"));
+        assert!(!bl.contains("mango"));
+    }
+
+    #[test]
+    fn test_try_insert_reports_overflow() {
+        let mut bl = CountingBloomFilter::with_counter_width(10, 0.01, 2);
+        (0..3).for_each(|_| bl.try_insert("mango").unwrap());
+
+        assert!(bl.try_insert("mango").is_err());
+        assert_eq!(bl.overflows(), 0);
+    }
+
+    #[test]
+    fn test_saturated_counters_are_sticky() {
+        let mut bl = CountingBloomFilter::with_counter_width(10, 0.01, 1);
+        bl.insert("mango");
+        bl.insert("mango");
+
+        assert!(bl.overflows() > 0);
+        assert!(bl.remove("mango"));
+        assert!(bl.contains("mango")); // no false negative, the count is unknown
+    }
+}
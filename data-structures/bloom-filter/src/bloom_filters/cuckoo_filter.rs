@@ -0,0 +1,354 @@
+use bitvec::prelude::*;
+
+use crate::bloom_filter::{BloomFilter, DeletableBloomFilter, ReadableBloomFilter};
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::rng::SplitMix64;
+
+const DEFAULT_BUCKET_SIZE: usize = 4;
+const DEFAULT_MAX_KICKS: usize = 500;
+/// Cuckoo filters with 4-slot buckets fill up at about 95% occupancy.
+const LOAD_FACTOR: f64 = 0.95;
+const EMPTY: u32 = 0;
+
+/// Returned by `try_insert` once an earlier insert had to park a victim, i.e. the filter is full.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CuckooFilterFull;
+
+impl std::fmt::Display for CuckooFilterFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cuckoo filter is full")
+    }
+}
+
+impl std::error::Error for CuckooFilterFull {}
+
+/// Cuckoo filter (Fan et al.): stores a short fingerprint of every key in one of two buckets.
+///
+/// Below roughly 3% false positives it is smaller than a bloom filter, and it supports `remove`.
+/// The false positive rate is about `2 * bucket_size / 2^fingerprint_bits`.
+///
+/// When relocation gives up after `max_kicks` moves, the homeless fingerprint is parked in a
+/// single victim slot so nothing is lost; while that slot is taken the filter counts as full.
+#[derive(Debug, Clone)]
+pub struct CuckooFilter<H: KeyHasher = SeaHash> {
+    fingerprints: BitVec,
+    fingerprint_bits: usize,
+    bucket_size: usize,
+    bucket_count: usize,
+    max_kicks: usize,
+    victim: Option<(usize, u32)>,
+    len: usize,
+    rng: SplitMix64,
+    hasher: H,
+}
+
+impl CuckooFilter {
+    /// Picks the fingerprint size for the false positive rate with 4-slot buckets.
+    pub fn new(elements: usize, false_probability: f32) -> Self {
+        // p <= 2b / 2^f  =>  f = log2(2b / p)
+        let fingerprint_bits = (2.0 * DEFAULT_BUCKET_SIZE as f64 / false_probability as f64)
+            .log2()
+            .ceil() as usize;
+
+        Self::with_parameters(elements, fingerprint_bits.clamp(1, 32), DEFAULT_BUCKET_SIZE)
+    }
+
+    /// `fingerprint_bits` is between 1 and 32. The bucket count is rounded up to a power of two.
+    pub fn with_parameters(elements: usize, fingerprint_bits: usize, bucket_size: usize) -> Self {
+        Self::with_hasher(elements, fingerprint_bits, bucket_size, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> CuckooFilter<H> {
+    /// Like `with_parameters`, hashing keys with `hasher`. Only the key is hashed with it,
+    /// the fingerprint-to-bucket mapping always uses seahash.
+    pub fn with_hasher(
+        elements: usize,
+        fingerprint_bits: usize,
+        bucket_size: usize,
+        hasher: H,
+    ) -> Self {
+        assert!(
+            (1..=32).contains(&fingerprint_bits),
+            "fingerprint size must be between 1 and 32 bits"
+        );
+        assert!(bucket_size > 0, "buckets need at least one slot");
+
+        let buckets = (elements as f64 / (bucket_size as f64 * LOAD_FACTOR)).ceil() as usize;
+        let bucket_count = buckets.max(1).next_power_of_two();
+
+        Self {
+            fingerprints: bitvec![0; bucket_count * bucket_size * fingerprint_bits],
+            fingerprint_bits,
+            bucket_size,
+            bucket_count,
+            max_kicks: DEFAULT_MAX_KICKS,
+            victim: None,
+            len: 0,
+            rng: SplitMix64::new(0),
+            hasher,
+        }
+    }
+
+    /// Bounds how many fingerprints one insert may relocate before giving up.
+    pub fn with_max_kicks(mut self, max_kicks: usize) -> Self {
+        self.max_kicks = max_kicks;
+        self
+    }
+
+    /// Inserts the key, relocating up to `max_kicks` fingerprints to make room. When they run
+    /// out the last evicted fingerprint is parked as the victim and the insert still succeeds,
+    /// every key stays found. From then on the filter is full: inserts fail with
+    /// `CuckooFilterFull` and change nothing until a `remove` frees a slot.
+    pub fn try_insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> Result<(), CuckooFilterFull> {
+        if self.victim.is_some() {
+            return Err(CuckooFilterFull);
+        }
+
+        let (fingerprint, first) = self.locate(key.as_ref());
+        let second = self.alternate_bucket(first, fingerprint);
+
+        if self.put(first, fingerprint) || self.put(second, fingerprint) {
+            self.len += 1;
+            return Ok(());
+        }
+
+        let mut bucket = if self.rng.below(2) == 0 {
+            first
+        } else {
+            second
+        };
+        let mut fingerprint = fingerprint;
+        for _ in 0..self.max_kicks {
+            let slot = bucket * self.bucket_size + self.rng.below(self.bucket_size);
+            let evicted = self.slot(slot);
+            self.set_slot(slot, fingerprint);
+            fingerprint = evicted;
+
+            bucket = self.alternate_bucket(bucket, fingerprint);
+            if self.put(bucket, fingerprint) {
+                self.len += 1;
+                return Ok(());
+            }
+        }
+
+        self.victim = Some((bucket, fingerprint));
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bucket_count * self.bucket_size
+    }
+
+    /// Fingerprint (never `EMPTY`) and first bucket of a key.
+    fn locate(&self, key: &[u8]) -> (u32, usize) {
+        let hash = self.hasher.hash(key, 0);
+        let mask = (u64::MAX >> (64 - self.fingerprint_bits)) as u32;
+        let fingerprint = ((hash >> 32) as u32 & mask).max(1);
+
+        (fingerprint, hash as usize & (self.bucket_count - 1))
+    }
+
+    /// Partial-key cuckoo hashing: each bucket can be computed from the other and the fingerprint.
+    fn alternate_bucket(&self, bucket: usize, fingerprint: u32) -> usize {
+        let hash = seahash::hash(&fingerprint.to_le_bytes()) as usize;
+        (bucket ^ hash) & (self.bucket_count - 1)
+    }
+
+    fn slot(&self, slot: usize) -> u32 {
+        let start = slot * self.fingerprint_bits;
+        self.fingerprints[start..start + self.fingerprint_bits].load::<u32>()
+    }
+
+    fn set_slot(&mut self, slot: usize, fingerprint: u32) {
+        let start = slot * self.fingerprint_bits;
+        self.fingerprints[start..start + self.fingerprint_bits].store::<u32>(fingerprint);
+    }
+
+    fn slots(&self, bucket: usize) -> std::ops::Range<usize> {
+        bucket * self.bucket_size..(bucket + 1) * self.bucket_size
+    }
+
+    /// Stores the fingerprint in a free slot of the bucket, if there is one.
+    fn put(&mut self, bucket: usize, fingerprint: u32) -> bool {
+        match self.slots(bucket).find(|&slot| self.slot(slot) == EMPTY) {
+            Some(slot) => {
+                self.set_slot(slot, fingerprint);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bucket_contains(&self, bucket: usize, fingerprint: u32) -> bool {
+        self.slots(bucket)
+            .any(|slot| self.slot(slot) == fingerprint)
+    }
+
+    fn take(&mut self, bucket: usize, fingerprint: u32) -> bool {
+        match self
+            .slots(bucket)
+            .find(|&slot| self.slot(slot) == fingerprint)
+        {
+            Some(slot) => {
+                self.set_slot(slot, EMPTY);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<H: KeyHasher> BloomFilter for CuckooFilter<H> {
+    /// Panics when the filter is full, use `try_insert` to handle that case.
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        self.try_insert(key).expect("cuckoo filter is full")
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for CuckooFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        let (fingerprint, first) = self.locate(key.as_ref());
+        let second = self.alternate_bucket(first, fingerprint);
+
+        self.victim == Some((first, fingerprint))
+            || self.victim == Some((second, fingerprint))
+            || self.bucket_contains(first, fingerprint)
+            || self.bucket_contains(second, fingerprint)
+    }
+}
+
+impl<H: KeyHasher> DeletableBloomFilter for CuckooFilter<H> {
+    fn remove<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> bool {
+        let (fingerprint, first) = self.locate(key.as_ref());
+        let second = self.alternate_bucket(first, fingerprint);
+
+        if self.victim == Some((first, fingerprint)) || self.victim == Some((second, fingerprint)) {
+            self.victim = None;
+            self.len -= 1;
+            return true;
+        }
+
+        if !self.take(first, fingerprint) && !self.take(second, fingerprint) {
+            return false;
+        }
+        self.len -= 1;
+
+        // a slot just freed up, give the parked victim another chance
+        if let Some((bucket, fingerprint)) = self.victim.take() {
+            let other = self.alternate_bucket(bucket, fingerprint);
+            if !self.put(bucket, fingerprint) && !self.put(other, fingerprint) {
+                self.victim = Some((bucket, fingerprint));
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_empty() {
+        let cf = CuckooFilter::new(100, 0.01);
+        assert!(cf.is_empty());
+        assert!((0..cf.capacity()).all(|slot| cf.slot(slot) == EMPTY));
+        assert!(cf.bucket_count.is_power_of_two());
+    }
+
+    #[test]
+    fn test_fingerprint_size_from_false_probability() {
+        // 2 * 4 / 0.01 = 800 -> 10 bits
+        assert_eq!(CuckooFilter::new(100, 0.01).fingerprint_bits, 10);
+        assert_eq!(CuckooFilter::new(100, 0.001).fingerprint_bits, 13);
+    }
+
+    #[test]
+    fn test_insert_contains_remove() {
+        let mut cf = CuckooFilter::new(100, 0.01);
+        cf.insert("mango");
+        cf.insert("apple");
+
+        assert!(cf.contains("mango"));
+        assert!(cf.remove("mango"));
+        assert!(!cf.contains("mango"));
+        assert!(cf.contains("apple"));
+        assert!(!cf.remove("orange"));
+        assert_eq!(cf.len(), 1);
+    }
+
+    #[test]
+    fn test_alternate_bucket_is_involution() {
+        let cf = CuckooFilter::new(1000, 0.01);
+        let (fingerprint, first) = cf.locate(b"mango");
+        let second = cf.alternate_bucket(first, fingerprint);
+
+        assert_eq!(cf.alternate_bucket(second, fingerprint), first);
+    }
+
+    #[test]
+    fn test_full_filter_reports_error() {
+        let mut cf = CuckooFilter::with_parameters(8, 8, 2).with_max_kicks(50);
+
+        let inserted = (0..1000)
+            .map(|i| format!("key-{i}"))
+            .take_while(|key| cf.try_insert(key).is_ok())
+            .collect::<Vec<_>>();
+
+        assert!(cf.try_insert("one-more").is_err());
+        assert!(inserted.len() >= cf.capacity() / 2);
+        // the key that overflowed into the victim slot is still found
+        assert!(inserted.iter().all(|key| cf.contains(key)));
+    }
+
+    #[test]
+    fn test_failed_insert_changes_nothing() {
+        let mut cf = CuckooFilter::with_parameters(8, 8, 2).with_max_kicks(50);
+        let inserted = (0..1000)
+            .map(|i| format!("key-{i}"))
+            .take_while(|key| cf.try_insert(key).is_ok())
+            .collect::<Vec<_>>();
+
+        let (fingerprints, victim, len) = (cf.fingerprints.clone(), cf.victim, cf.len);
+        assert_eq!(cf.try_insert("one-more"), Err(CuckooFilterFull));
+
+        assert_eq!(cf.fingerprints, fingerprints);
+        assert_eq!((cf.victim, cf.len), (victim, len));
+        assert!(inserted.iter().all(|key| cf.contains(key)));
+    }
+
+    #[test]
+    fn test_remove_frees_up_space() {
+        let mut cf = CuckooFilter::with_parameters(8, 8, 2).with_max_kicks(50);
+        let inserted = (0..1000)
+            .map(|i| format!("key-{i}"))
+            .take_while(|key| cf.try_insert(key).is_ok())
+            .collect::<Vec<_>>();
+
+        assert!(cf.remove(&inserted[0]));
+        assert!(inserted[1..].iter().all(|key| cf.contains(key)));
+        assert_eq!(cf.len(), inserted.len() - 1);
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut cf = CuckooFilter::new(10_000, 0.01);
+        (0..10_000).for_each(|i| cf.insert(&format!("key-{i}")));
+        assert!((0..10_000).all(|i| cf.contains(&format!("key-{i}"))));
+
+        let false_positives = (0..100_000)
+            .filter(|i| cf.contains(&format!("probe-{i}")))
+            .count();
+        assert!(false_positives < 1_000, "{false_positives} false positives");
+    }
+}
@@ -0,0 +1,602 @@
+use std::io::{Read, Write};
+
+use crate::bloom_filters::bloom_filter_prod::Indices;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::{
+    ChecksumReader, ChecksumWriter, HashAlgorithm, Header, SerializationError,
+};
+
+const MAGIC: [u8; 4] = *b"IBLT";
+/// Small differences fail to decode mostly when two keys share all their cells, which more
+/// hashes make rarer. With 2 cells per key, decoding fails under 0.3% of the time at any
+/// difference, and practically never from a few hundred keys on.
+const DEFAULT_HASH_COUNT: usize = 5;
+const CELLS_PER_ENTRY: f64 = 2.0;
+const MIN_CELLS_PER_HASH: usize = 8;
+pub const MAX_KEY_LEN: usize = 1 << 16;
+/// Far from the index seeds `0..hash_count`.
+const CHECKSUM_SEED: u64 = u64::MAX;
+
+/// Returned by `subtract` when two tables don't share their parameters.
+#[derive(Debug, PartialEq, Eq)]
+pub enum IncompatibleTables {
+    CellCount {
+        left: usize,
+        right: usize,
+    },
+    KeyLength {
+        left: usize,
+        right: usize,
+    },
+    HashCount {
+        left: usize,
+        right: usize,
+    },
+    /// Same hash function, different key, e.g. two `SipHash24` tables.
+    Hasher,
+}
+
+impl std::fmt::Display for IncompatibleTables {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CellCount { left, right } => {
+                write!(f, "cell counts differ: {left} vs {right}")
+            }
+            Self::KeyLength { left, right } => {
+                write!(f, "key lengths differ: {left} vs {right}")
+            }
+            Self::HashCount { left, right } => {
+                write!(f, "hash counts differ: {left} vs {right}")
+            }
+            Self::Hasher => write!(f, "hashers are keyed differently"),
+        }
+    }
+}
+
+impl std::error::Error for IncompatibleTables {}
+
+/// Keys recovered by `list_entries`. After `a.subtract(&b)`, `inserted` holds the keys only
+/// in `a` and `deleted` the keys only in `b`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Entries {
+    pub inserted: Vec<Vec<u8>>,
+    pub deleted: Vec<Vec<u8>>,
+}
+
+/// Returned by `list_entries` when peeling gets stuck, almost always because the table
+/// holds more keys than it was sized for. `partial` are the keys recovered before that.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DecodeFailure {
+    pub partial: Entries,
+    pub undecoded_cells: usize,
+}
+
+impl std::fmt::Display for DecodeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} cells could not be decoded, the table holds more keys than it has room for",
+            self.undecoded_cells
+        )
+    }
+}
+
+impl std::error::Error for DecodeFailure {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Cell {
+    count: i64,
+    len_sum: u32,
+    hash_sum: u64,
+}
+
+/// Invertible Bloom lookup table (Goodrich & Mitzenmacher): every key is added to one cell
+/// in each of `hash_count` equal parts of the table, picked by seeded hashing like
+/// `BloomFilterProd`'s. A cell keeps the count, and the xor of the keys, their lengths and
+/// their checksums, so a cell holding a single key gives it back.
+///
+/// Subtracting the table of another replica cancels every shared key, leaving a table sized
+/// by the difference alone, which `list_entries` decodes by repeatedly peeling single keys.
+#[derive(Debug, Clone)]
+pub struct InvertibleBloomLookupTable<H: KeyHasher = SeaHash> {
+    cells: Vec<Cell>,
+    /// `key_len` bytes per cell.
+    key_sums: Vec<u8>,
+    key_len: usize,
+    hash_count: usize,
+    hasher: H,
+}
+
+impl InvertibleBloomLookupTable {
+    /// Sized to decode up to `difference` keys of at most `key_len` bytes.
+    pub fn new(difference: usize, key_len: usize) -> Self {
+        Self::with_hasher(difference, key_len, SeaHash)
+    }
+
+    /// Reads a seahash table written by `write_to`, see `read_with_hasher`.
+    pub fn read_from(reader: impl Read) -> Result<Self, SerializationError> {
+        Self::read_with_hasher(reader, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> InvertibleBloomLookupTable<H> {
+    pub fn with_hasher(difference: usize, key_len: usize, hasher: H) -> Self {
+        let cells_per_hash = (difference as f64 * CELLS_PER_ENTRY / DEFAULT_HASH_COUNT as f64)
+            .ceil()
+            .max(MIN_CELLS_PER_HASH as f64) as usize;
+
+        Self::with_parameters(
+            cells_per_hash * DEFAULT_HASH_COUNT,
+            DEFAULT_HASH_COUNT,
+            key_len,
+            hasher,
+        )
+    }
+
+    /// `cell_count` must be a multiple of `hash_count`.
+    pub fn with_parameters(
+        cell_count: usize,
+        hash_count: usize,
+        key_len: usize,
+        hasher: H,
+    ) -> Self {
+        assert!(hash_count > 0, "hash count must be positive");
+        assert!(
+            cell_count > 0 && cell_count.is_multiple_of(hash_count),
+            "cell count must be a positive multiple of the hash count"
+        );
+        assert!(
+            key_len <= MAX_KEY_LEN,
+            "key length must be at most {MAX_KEY_LEN} bytes"
+        );
+
+        Self {
+            cells: vec![Cell::default(); cell_count],
+            key_sums: vec![0; cell_count * key_len],
+            key_len,
+            hash_count,
+            hasher,
+        }
+    }
+
+    /// Panics if the key is longer than `key_len`.
+    pub fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        self.update(key.as_ref(), 1);
+    }
+
+    /// Removes a key, or records it as missing if it was never inserted.
+    pub fn delete<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        self.update(key.as_ref(), -1);
+    }
+
+    /// Deletes every key of `other` from this table, leaving the keys only one side has.
+    pub fn subtract(&mut self, other: &Self) -> Result<(), IncompatibleTables> {
+        self.check_compatible(other)?;
+
+        for (cell, other) in self.cells.iter_mut().zip(&other.cells) {
+            cell.count = cell.count.wrapping_sub(other.count);
+            cell.len_sum ^= other.len_sum;
+            cell.hash_sum ^= other.hash_sum;
+        }
+        xor_into(&mut self.key_sums, &other.key_sums);
+        Ok(())
+    }
+
+    /// Recovers every key inserted and not deleted, and every key deleted and not inserted.
+    pub fn list_entries(&self) -> Result<Entries, DecodeFailure> {
+        let mut table = self.clone();
+        let mut entries = Entries::default();
+        let mut pure = (0..table.cells.len())
+            .filter(|&index| table.pure_key(index).is_some())
+            .collect::<Vec<_>>();
+
+        while let Some(index) = pure.pop() {
+            // a queued cell may have been emptied by peeling another one since
+            let Some((key, count)) = table.pure_key(index) else {
+                continue;
+            };
+
+            for index in table.update(&key, -count) {
+                if table.pure_key(index).is_some() {
+                    pure.push(index);
+                }
+            }
+            match count {
+                1 => entries.inserted.push(key),
+                _ => entries.deleted.push(key),
+            }
+        }
+
+        let undecoded_cells = (0..table.cells.len())
+            .filter(|&index| !table.is_empty_cell(index))
+            .count();
+        match undecoded_cells {
+            0 => Ok(entries),
+            _ => Err(DecodeFailure {
+                partial: entries,
+                undecoded_cells,
+            }),
+        }
+    }
+
+    /// No keys, or every insert undone by a delete.
+    pub fn is_empty(&self) -> bool {
+        (0..self.cells.len()).all(|index| self.is_empty_cell(index))
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn hash_count(&self) -> usize {
+        self.hash_count
+    }
+
+    pub fn key_len(&self) -> usize {
+        self.key_len
+    }
+
+    pub fn check_compatible(&self, other: &Self) -> Result<(), IncompatibleTables> {
+        if self.cells.len() != other.cells.len() {
+            return Err(IncompatibleTables::CellCount {
+                left: self.cells.len(),
+                right: other.cells.len(),
+            });
+        }
+        if self.key_len != other.key_len {
+            return Err(IncompatibleTables::KeyLength {
+                left: self.key_len,
+                right: other.key_len,
+            });
+        }
+        if self.hash_count != other.hash_count {
+            return Err(IncompatibleTables::HashCount {
+                left: self.hash_count,
+                right: other.hash_count,
+            });
+        }
+        if self.hasher != other.hasher {
+            return Err(IncompatibleTables::Hasher);
+        }
+        Ok(())
+    }
+
+    /// Writes the table in the format described by `serialization::Header`, whose bit length
+    /// holds the cell count. The key length (u32) and the cells follow: count (i64),
+    /// length sum (u32), checksum sum (u64) and key sum, all little endian.
+    pub fn write_to(&self, writer: impl Write) -> Result<(), SerializationError> {
+        let mut writer = ChecksumWriter::new(writer);
+
+        Header {
+            magic: MAGIC,
+            hash_algorithm: HashAlgorithm::Seeded,
+            hash_function: H::FUNCTION,
+            hash_count: self.hash_count as u32,
+            bit_len: self.cells.len() as u64,
+        }
+        .write(&mut writer)?;
+
+        writer.write_all(&(self.key_len as u32).to_le_bytes())?;
+        for (index, cell) in self.cells.iter().enumerate() {
+            writer.write_u64(cell.count as u64)?;
+            writer.write_all(&cell.len_sum.to_le_bytes())?;
+            writer.write_u64(cell.hash_sum)?;
+            writer.write_all(self.key_sum(index))?;
+        }
+
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Reads a table written by `write_to`, rejecting corrupted or incompatible input
+    /// and tables built with another hash function than `hasher`'s.
+    pub fn read_with_hasher(reader: impl Read, hasher: H) -> Result<Self, SerializationError> {
+        let mut reader = ChecksumReader::new(reader);
+        let header = Header::read(&mut reader, MAGIC)?;
+        if header.hash_function != H::FUNCTION {
+            return Err(SerializationError::HashFunctionMismatch {
+                expected: H::FUNCTION,
+                found: header.hash_function,
+            });
+        }
+        if header.hash_algorithm != HashAlgorithm::Seeded {
+            return Err(SerializationError::InvalidParameters(
+                "cells are only indexed by seeded hashing",
+            ));
+        }
+
+        let cell_count = header.bit_len as usize;
+        let hash_count = header.hash_count as usize;
+        if !cell_count.is_multiple_of(hash_count) {
+            return Err(SerializationError::InvalidParameters(
+                "cell count is not a multiple of the hash count",
+            ));
+        }
+        let key_len = reader.read_u32()? as usize;
+        if key_len > MAX_KEY_LEN {
+            return Err(SerializationError::InvalidParameters(
+                "key length too large",
+            ));
+        }
+
+        // cells are pushed as they arrive so a lying header can't force a huge allocation
+        let mut cells = Vec::new();
+        let mut key_sums = Vec::new();
+        let mut key_sum = vec![0; key_len];
+        for _ in 0..cell_count {
+            cells.push(Cell {
+                count: reader.read_u64()? as i64,
+                len_sum: reader.read_u32()?,
+                hash_sum: reader.read_u64()?,
+            });
+            reader.read_exact(&mut key_sum)?;
+            key_sums.extend_from_slice(&key_sum);
+        }
+        reader.verify()?;
+
+        Ok(Self {
+            cells,
+            key_sums,
+            key_len,
+            hash_count,
+            hasher,
+        })
+    }
+
+    /// Adds `count` copies of the key to its cells, returning them.
+    fn update(&mut self, key: &[u8], count: i64) -> Vec<usize> {
+        assert!(
+            key.len() <= self.key_len,
+            "key of {} bytes is longer than the table's {}",
+            key.len(),
+            self.key_len
+        );
+
+        let checksum = self.hasher.hash(key, CHECKSUM_SEED);
+        let indices = self.indices(key);
+        for &index in &indices {
+            let cell = &mut self.cells[index];
+            cell.count = cell.count.wrapping_add(count);
+            cell.len_sum ^= key.len() as u32;
+            cell.hash_sum ^= checksum;
+            let start = index * self.key_len;
+            xor_into(&mut self.key_sums[start..start + key.len()], key);
+        }
+        indices
+    }
+
+    /// One cell in each part of the table, so a key never lands twice in the same cell.
+    fn indices(&self, key: &[u8]) -> Vec<usize> {
+        let part_len = self.cells.len() / self.hash_count;
+        Indices::new(
+            key,
+            self.hasher.clone(),
+            HashAlgorithm::Seeded,
+            self.hash_count,
+            part_len,
+        )
+        .enumerate()
+        .map(|(part, index)| part * part_len + index)
+        .collect()
+    }
+
+    /// The key and its sign if the cell holds exactly one key, inserted or deleted.
+    fn pure_key(&self, index: usize) -> Option<(Vec<u8>, i64)> {
+        let cell = self.cells[index];
+        let len = cell.len_sum as usize;
+        if !matches!(cell.count, 1 | -1) || len > self.key_len {
+            return None;
+        }
+
+        let (key, padding) = self.key_sum(index).split_at(len);
+        if padding.iter().any(|&byte| byte != 0)
+            || self.hasher.hash(key, CHECKSUM_SEED) != cell.hash_sum
+        {
+            return None;
+        }
+        Some((key.to_vec(), cell.count))
+    }
+
+    fn is_empty_cell(&self, index: usize) -> bool {
+        self.cells[index] == Cell::default() && self.key_sum(index).iter().all(|&byte| byte == 0)
+    }
+
+    fn key_sum(&self, index: usize) -> &[u8] {
+        &self.key_sums[index * self.key_len..(index + 1) * self.key_len]
+    }
+}
+
+fn xor_into(target: &mut [u8], source: &[u8]) {
+    target
+        .iter_mut()
+        .zip(source)
+        .for_each(|(target, source)| *target ^= source);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::{Fnv1a, SipHash24};
+    use crate::rng::SplitMix64;
+
+    fn sorted(mut keys: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        keys.sort();
+        keys
+    }
+
+    fn random_keys(rng: &mut SplitMix64, count: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|_| rng.next_u64().to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_then_delete_is_empty() {
+        let mut table = InvertibleBloomLookupTable::new(10, 8);
+        assert!(table.is_empty());
+
+        table.insert("mango");
+        table.insert("apple");
+        assert!(!table.is_empty());
+        table.delete("mango");
+        table.delete("apple");
+
+        assert!(table.is_empty());
+        assert_eq!(table.list_entries(), Ok(Entries::default()));
+    }
+
+    #[test]
+    fn test_list_entries() {
+        let mut table = InvertibleBloomLookupTable::new(10, 16);
+        let keys = ["", "a", "ab", "ba", "mango", "a longer key..."];
+        keys.iter().for_each(|key| table.insert(key));
+        table.delete("never inserted");
+
+        let entries = table.list_entries().unwrap();
+        assert_eq!(
+            sorted(entries.inserted),
+            sorted(keys.iter().map(|key| key.as_bytes().to_vec()).collect())
+        );
+        assert_eq!(entries.deleted, vec![b"never inserted".to_vec()]);
+    }
+
+    #[test]
+    fn test_subtract_finds_symmetric_difference() {
+        let mut rng = SplitMix64::new(42);
+        let shared = random_keys(&mut rng, 10_000);
+        let only_local = random_keys(&mut rng, 30);
+        let only_remote = random_keys(&mut rng, 20);
+
+        let mut local = InvertibleBloomLookupTable::new(50, 8);
+        let mut remote = local.clone();
+        shared
+            .iter()
+            .chain(&only_local)
+            .for_each(|key| local.insert(key));
+        shared
+            .iter()
+            .chain(&only_remote)
+            .for_each(|key| remote.insert(key));
+
+        local.subtract(&remote).unwrap();
+        let entries = local.list_entries().unwrap();
+        assert_eq!(sorted(entries.inserted), sorted(only_local));
+        assert_eq!(sorted(entries.deleted), sorted(only_remote));
+    }
+
+    #[test]
+    fn test_decodes_at_capacity() {
+        let mut rng = SplitMix64::new(7);
+        for difference in [1, 10, 100, 1000] {
+            for _ in 0..20 {
+                let keys = random_keys(&mut rng, difference);
+                let mut table = InvertibleBloomLookupTable::new(difference, 8);
+                keys.iter().for_each(|key| table.insert(key));
+
+                let entries = table.list_entries().unwrap();
+                assert_eq!(sorted(entries.inserted), sorted(keys));
+            }
+        }
+    }
+
+    #[test]
+    fn test_reports_decode_failure_beyond_capacity() {
+        let mut rng = SplitMix64::new(7);
+        let keys = random_keys(&mut rng, 500);
+        let mut table = InvertibleBloomLookupTable::new(50, 8);
+        keys.iter().for_each(|key| table.insert(key));
+
+        let failure = table.list_entries().unwrap_err();
+        assert!(failure.undecoded_cells > 0);
+        assert!(failure.partial.deleted.is_empty());
+        assert!(failure
+            .partial
+            .inserted
+            .iter()
+            .all(|key| keys.contains(key)));
+    }
+
+    #[test]
+    fn test_subtract_rejects_incompatible_tables() {
+        let mut table = InvertibleBloomLookupTable::new(100, 8);
+
+        assert_eq!(
+            table.subtract(&InvertibleBloomLookupTable::new(10, 8)),
+            Err(IncompatibleTables::CellCount {
+                left: 200,
+                right: 40
+            })
+        );
+        assert_eq!(
+            table.subtract(&InvertibleBloomLookupTable::new(100, 16)),
+            Err(IncompatibleTables::KeyLength { left: 8, right: 16 })
+        );
+        assert_eq!(
+            table.subtract(&InvertibleBloomLookupTable::with_parameters(
+                200, 4, 8, SeaHash
+            )),
+            Err(IncompatibleTables::HashCount { left: 5, right: 4 })
+        );
+
+        let mut keyed = InvertibleBloomLookupTable::with_hasher(100, 8, SipHash24::new([1; 16]));
+        let other = InvertibleBloomLookupTable::with_hasher(100, 8, SipHash24::new([2; 16]));
+        assert_eq!(keyed.subtract(&other), Err(IncompatibleTables::Hasher));
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let mut table = InvertibleBloomLookupTable::new(20, 8);
+        (0..15u64).for_each(|key| table.insert(&key.to_le_bytes()));
+        table.delete("gone");
+
+        let mut bytes = Vec::new();
+        table.write_to(&mut bytes).unwrap();
+        let read_back = InvertibleBloomLookupTable::read_from(&bytes[..]).unwrap();
+
+        assert_eq!(read_back.cells, table.cells);
+        assert_eq!(read_back.key_sums, table.key_sums);
+        assert_eq!(read_back.list_entries(), table.list_entries());
+    }
+
+    #[test]
+    fn test_serialization_hash_count_limit() {
+        let mut table = InvertibleBloomLookupTable::with_parameters(64 * 8, 64, 8, SeaHash);
+        table.insert("mango");
+        let mut bytes = Vec::new();
+        table.write_to(&mut bytes).unwrap();
+        let read_back = InvertibleBloomLookupTable::read_from(&bytes[..]).unwrap();
+        assert_eq!(read_back.cells, table.cells);
+
+        let table = InvertibleBloomLookupTable::with_parameters(65 * 8, 65, 8, SeaHash);
+        assert!(matches!(
+            table.write_to(&mut Vec::new()),
+            Err(SerializationError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_rejects_corruption() {
+        let mut table = InvertibleBloomLookupTable::new(20, 8);
+        table.insert("mango");
+        let mut bytes = Vec::new();
+        table.write_to(&mut bytes).unwrap();
+
+        let mut flipped = bytes.clone();
+        flipped[30] ^= 1;
+        assert!(matches!(
+            InvertibleBloomLookupTable::read_from(&flipped[..]),
+            Err(SerializationError::ChecksumMismatch { .. })
+        ));
+        assert!(InvertibleBloomLookupTable::read_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(matches!(
+            InvertibleBloomLookupTable::read_with_hasher(&bytes[..], Fnv1a),
+            Err(SerializationError::HashFunctionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "key of 9 bytes is longer than the table's 8")]
+    fn test_key_too_long() {
+        InvertibleBloomLookupTable::new(10, 8).insert("too long!");
+    }
+}
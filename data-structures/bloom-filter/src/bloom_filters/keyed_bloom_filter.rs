@@ -0,0 +1,315 @@
+use std::io::{Read, Write};
+
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::hashing::{SecretKeyHasher, SipHash24};
+use crate::serialization::{
+    ChecksumReader, ChecksumWriter, HashAlgorithm, HashFunction, SerializationError,
+};
+
+const MAGIC: [u8; 4] = *b"BLMK";
+const VERSION: u16 = 1;
+const KEY_WITHHELD: u8 = 1;
+const KEY_INCLUDED: u8 = 2;
+/// Hashed under the secret key to tell a wrong key from a right one without storing it.
+const KEY_CHECK: &[u8] = b"keyed bloom filter key check";
+const KEY_CHECK_SEED: u64 = u64::MAX;
+
+/// `BloomFilterProd` hashing under a secret key, e.g. `SipHash24` (128 bits) or
+/// `DoubleSipHash24` (256 bits), so nobody without the key can predict which bits a key sets
+/// and craft keys that fill the filter up.
+///
+/// The key stays out of the serialized filter unless written with `write_including_key`.
+#[derive(Debug, Clone)]
+pub struct KeyedBloomFilter<H: SecretKeyHasher = SipHash24> {
+    filter: BloomFilterProd<H>,
+}
+
+impl<H: SecretKeyHasher> KeyedBloomFilter<H> {
+    /// Sized like `BloomFilterProd::new`, with `HashAlgorithm::Double` since keyed hashes cost
+    /// more than seahash.
+    pub fn new(elements: usize, false_probability: f32, hasher: H) -> Self {
+        Self::from_filter(BloomFilterProd::with_hasher(
+            elements,
+            false_probability,
+            HashAlgorithm::Double,
+            hasher,
+        ))
+    }
+
+    pub fn from_filter(filter: BloomFilterProd<H>) -> Self {
+        Self { filter }
+    }
+
+    pub fn filter(&self) -> &BloomFilterProd<H> {
+        &self.filter
+    }
+
+    pub fn into_filter(self) -> BloomFilterProd<H> {
+        self.filter
+    }
+
+    /// Writes the filter without its key, only a check value that tells `read_with_key` when
+    /// it is given the wrong one.
+    ///
+    /// magic (4) | version (u16) | hash function (u8) | key mode (u8, 1 withheld, 2 included) |
+    /// key length (u16) | key check (u64) or the key | the filter as `BloomFilterProd::write_to`
+    /// writes it | seahash checksum (u64).
+    pub fn write_to(&self, writer: impl Write) -> Result<(), SerializationError> {
+        self.write(writer, false)
+    }
+
+    /// Writes the filter along with its secret key, for storage as trusted as the key itself.
+    pub fn write_including_key(&self, writer: impl Write) -> Result<(), SerializationError> {
+        self.write(writer, true)
+    }
+
+    /// Reads a filter written by either write method, failing unless `hasher` holds the key it
+    /// was written with.
+    pub fn read_with_key(reader: impl Read, hasher: H) -> Result<Self, SerializationError> {
+        let mut reader = ChecksumReader::new(reader);
+        let stored = read_key(&mut reader, H::FUNCTION, H::KEY_LEN)?;
+        let matches = match stored {
+            StoredKey::Check(check) => hasher.hash(KEY_CHECK, KEY_CHECK_SEED) == check,
+            StoredKey::Key(key) => hasher.key_bytes() == key,
+        };
+        if !matches {
+            return Err(SerializationError::KeyMismatch);
+        }
+
+        let filter = BloomFilterProd::read_with_hasher(&mut reader, hasher)?;
+        reader.verify()?;
+        Ok(Self { filter })
+    }
+
+    /// Reads a filter written by `write_including_key`, taking the key from the stream.
+    pub fn read_including_key(reader: impl Read) -> Result<Self, SerializationError> {
+        let mut reader = ChecksumReader::new(reader);
+        let StoredKey::Key(key) = read_key(&mut reader, H::FUNCTION, H::KEY_LEN)? else {
+            return Err(SerializationError::KeyNotIncluded);
+        };
+        let hasher = H::from_key_bytes(&key).expect("key length checked by read_key");
+
+        let filter = BloomFilterProd::read_with_hasher(&mut reader, hasher)?;
+        reader.verify()?;
+        Ok(Self { filter })
+    }
+
+    fn write(&self, writer: impl Write, include_key: bool) -> Result<(), SerializationError> {
+        let mut writer = ChecksumWriter::new(writer);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        let key_mode = if include_key {
+            KEY_INCLUDED
+        } else {
+            KEY_WITHHELD
+        };
+        writer.write_all(&[H::FUNCTION.id(), key_mode])?;
+        writer.write_all(&(H::KEY_LEN as u16).to_le_bytes())?;
+
+        let hasher = self.filter.hasher();
+        if include_key {
+            writer.write_all(&hasher.key_bytes())?;
+        } else {
+            writer.write_u64(hasher.hash(KEY_CHECK, KEY_CHECK_SEED))?;
+        }
+        self.filter.write_to(&mut writer)?;
+
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+enum StoredKey {
+    Check(u64),
+    Key(Vec<u8>),
+}
+
+fn read_key(
+    reader: &mut ChecksumReader<impl Read>,
+    function: HashFunction,
+    key_len: usize,
+) -> Result<StoredKey, SerializationError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(SerializationError::BadMagic(magic));
+    }
+    let version = reader.read_u16()?;
+    if version != VERSION {
+        return Err(SerializationError::UnsupportedVersion(version));
+    }
+    let found = HashFunction::from_id(reader.read_u8()?)?;
+    if found != function {
+        return Err(SerializationError::HashFunctionMismatch {
+            expected: function,
+            found,
+        });
+    }
+    let key_mode = reader.read_u8()?;
+    if reader.read_u16()? as usize != key_len {
+        return Err(SerializationError::InvalidParameters(
+            "key length differs from the hash function's",
+        ));
+    }
+
+    match key_mode {
+        KEY_WITHHELD => Ok(StoredKey::Check(reader.read_u64()?)),
+        KEY_INCLUDED => {
+            let mut key = vec![0; key_len];
+            reader.read_exact(&mut key)?;
+            Ok(StoredKey::Key(key))
+        }
+        _ => Err(SerializationError::InvalidParameters("unknown key mode")),
+    }
+}
+
+impl<H: SecretKeyHasher> BloomFilter for KeyedBloomFilter<H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        self.filter.insert(key);
+    }
+
+    fn insert_many<K: AsRef<[u8]>>(&mut self, keys: &[K]) {
+        self.filter.insert_many(keys);
+    }
+}
+
+impl<H: SecretKeyHasher> ReadableBloomFilter for KeyedBloomFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.filter.contains(key)
+    }
+
+    fn contains_many<K: AsRef<[u8]>>(&self, keys: &[K], results: &mut [bool]) {
+        self.filter.contains_many(keys, results);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::{DoubleSipHash24, KeyHasher, SeaHash};
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+    const WIDE_KEY: [u8; 32] = *b"0123456789abcdef0123456789abcdef";
+
+    fn filled<H: SecretKeyHasher>(hasher: H) -> KeyedBloomFilter<H> {
+        let mut filter = KeyedBloomFilter::new(1000, 0.01, hasher);
+        (0..500u64).for_each(|key| filter.insert(&key.to_le_bytes()));
+        filter
+    }
+
+    fn contains_subslice(bytes: &[u8], needle: &[u8]) -> bool {
+        bytes.windows(needle.len()).any(|window| window == needle)
+    }
+
+    /// Keys whose bits under `filter`'s hasher all fall in the first half of the filter, found
+    /// by an attacker who can compute the bits.
+    fn crafted_keys<H: KeyHasher>(filter: &BloomFilterProd<H>, count: usize) -> Vec<[u8; 8]> {
+        let half = filter.bit_count() / 2;
+        (0u64..)
+            .map(u64::to_le_bytes)
+            .filter(|key| {
+                let mut probe = BloomFilterProd::with_parameters(
+                    filter.bit_count(),
+                    filter.hash_count(),
+                    filter.hash_algorithm(),
+                    filter.hasher().clone(),
+                );
+                probe.insert(key);
+                probe.bits()[half..].not_any()
+            })
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn test_contains_inserted() {
+        let filter = filled(SipHash24::new(KEY));
+
+        assert!((0..500u64).all(|key| filter.contains(&key.to_le_bytes())));
+        assert_eq!(filter.filter().hash_algorithm(), HashAlgorithm::Double);
+    }
+
+    #[test]
+    fn test_crafted_keys_only_pollute_unkeyed_filters() {
+        let unkeyed = BloomFilterProd::with_hasher(1000, 0.01, HashAlgorithm::Double, SeaHash);
+        let keys = crafted_keys(&unkeyed, 300);
+
+        let mut victim = unkeyed.clone();
+        keys.iter().for_each(|key| victim.insert(key));
+        let half = victim.bit_count() / 2;
+        assert!(victim.bits()[half..].not_any());
+
+        // the same keys against a keyed filter spread like any others
+        let mut keyed = KeyedBloomFilter::new(1000, 0.01, SipHash24::new(KEY));
+        keys.iter().for_each(|key| keyed.insert(key));
+        let bits = keyed.filter().bits();
+        let upper = bits[half..].count_ones() as f64 / bits.count_ones() as f64;
+        assert!((0.4..0.6).contains(&upper), "{upper}");
+    }
+
+    #[test]
+    fn test_key_withheld_by_default() {
+        let filter = filled(SipHash24::new(KEY));
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+        assert!(!contains_subslice(&bytes, &KEY));
+
+        let read_back = KeyedBloomFilter::read_with_key(&bytes[..], SipHash24::new(KEY)).unwrap();
+        assert_eq!(read_back.filter().bits(), filter.filter().bits());
+
+        assert!(matches!(
+            KeyedBloomFilter::read_with_key(&bytes[..], SipHash24::new([0; 16])),
+            Err(SerializationError::KeyMismatch)
+        ));
+        assert!(matches!(
+            KeyedBloomFilter::<SipHash24>::read_including_key(&bytes[..]),
+            Err(SerializationError::KeyNotIncluded)
+        ));
+    }
+
+    #[test]
+    fn test_key_included_on_request() {
+        let filter = filled(DoubleSipHash24::new(WIDE_KEY));
+        let mut bytes = Vec::new();
+        filter.write_including_key(&mut bytes).unwrap();
+        assert!(contains_subslice(&bytes, &WIDE_KEY));
+
+        let read_back =
+            KeyedBloomFilter::<DoubleSipHash24>::read_including_key(&bytes[..]).unwrap();
+        assert_eq!(read_back.filter().hasher(), filter.filter().hasher());
+        assert!((0..500u64).all(|key| read_back.contains(&key.to_le_bytes())));
+
+        assert!(
+            KeyedBloomFilter::read_with_key(&bytes[..], DoubleSipHash24::new(WIDE_KEY)).is_ok()
+        );
+        assert!(matches!(
+            KeyedBloomFilter::read_with_key(&bytes[..], DoubleSipHash24::new([0; 32])),
+            Err(SerializationError::KeyMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_rejects_mismatch_and_corruption() {
+        let mut bytes = Vec::new();
+        filled(DoubleSipHash24::new(WIDE_KEY))
+            .write_to(&mut bytes)
+            .unwrap();
+
+        assert!(matches!(
+            KeyedBloomFilter::read_with_key(&bytes[..], SipHash24::new(KEY)),
+            Err(SerializationError::HashFunctionMismatch {
+                expected: HashFunction::SipHash24,
+                found: HashFunction::DoubleSipHash24,
+            })
+        ));
+
+        let mut flipped = bytes.clone();
+        let last = flipped.len() - 9;
+        flipped[last] ^= 1;
+        assert!(matches!(
+            KeyedBloomFilter::read_with_key(&flipped[..], DoubleSipHash24::new(WIDE_KEY)),
+            Err(SerializationError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,461 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use bitvec::prelude::*;
+use memmap2::{Mmap, MmapMut};
+
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::{BloomFilterProd, Indices};
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::{
+    ChecksumReader, ChecksumWriter, HashAlgorithm, Header, SerializationError,
+};
+
+const MAGIC: [u8; 4] = *b"BLMM";
+/// The bits start here, past the `serialization::Header` and padding that keeps them
+/// word aligned.
+const DATA_OFFSET: usize = 64;
+
+/// Parameters read from the header, shared by the read-only and writable filters.
+#[derive(Debug, Clone)]
+struct Layout<H> {
+    bit_count: usize,
+    hash_count: usize,
+    hash_algorithm: HashAlgorithm,
+    hasher: H,
+}
+
+impl<H: KeyHasher> Layout<H> {
+    /// Same bit layout as `BloomFilterProd`, packed into little endian `u64` words.
+    fn file_len(&self) -> usize {
+        DATA_OFFSET + self.bit_count.div_ceil(64) * 8
+    }
+
+    fn write_header(&self, map: &mut [u8]) -> Result<(), SerializationError> {
+        let mut header = Vec::new();
+        // the checksum is never finished, the bits change under it on every insert
+        Header {
+            magic: MAGIC,
+            hash_algorithm: self.hash_algorithm,
+            hash_function: H::FUNCTION,
+            hash_count: self.hash_count as u32,
+            bit_len: self.bit_count as u64,
+        }
+        .write(&mut ChecksumWriter::new(&mut header))?;
+        map[..header.len()].copy_from_slice(&header);
+        Ok(())
+    }
+
+    fn read(map: &[u8], hasher: H) -> Result<Self, SerializationError> {
+        let header = Header::read(&mut ChecksumReader::new(map), MAGIC)?;
+        if header.hash_function != H::FUNCTION {
+            return Err(SerializationError::HashFunctionMismatch {
+                expected: H::FUNCTION,
+                found: header.hash_function,
+            });
+        }
+        if !BloomFilterProd::supports(header.hash_algorithm) {
+            return Err(SerializationError::InvalidParameters(
+                "hash algorithm does not derive bit indices",
+            ));
+        }
+
+        let layout = Self {
+            bit_count: header.bit_len as usize,
+            hash_count: header.hash_count as usize,
+            hash_algorithm: header.hash_algorithm,
+            hasher,
+        };
+        if map.len() < layout.file_len() {
+            return Err(SerializationError::InvalidParameters(
+                "file is shorter than its header says",
+            ));
+        }
+        Ok(layout)
+    }
+
+    fn indices<'a>(&self, key: &'a [u8]) -> Indices<'a, H> {
+        Indices::new(
+            key,
+            self.hasher.clone(),
+            self.hash_algorithm,
+            self.hash_count,
+            self.bit_count,
+        )
+    }
+}
+
+fn locate(index: usize) -> (usize, u8) {
+    (DATA_OFFSET + index / 8, 1 << (index % 8))
+}
+
+/// Read-only bloom filter over a file written by `MmapBloomFilterMut`.
+///
+/// The bits are never copied: lookups read the mapped file, so opening is instant whatever
+/// the filter's size and every process that opens the same file shares one copy in the page
+/// cache. Inserts of a writer on the same file show up without reopening, `flush` only
+/// matters for durability.
+///
+/// The file must not be truncated while it is mapped; accessing a mapping past the end of
+/// its file kills the process with `SIGBUS`.
+#[derive(Debug)]
+pub struct MmapBloomFilter<H: KeyHasher = SeaHash> {
+    map: Mmap,
+    layout: Layout<H>,
+}
+
+impl MmapBloomFilter {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SerializationError> {
+        Self::open_with_hasher(path, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> MmapBloomFilter<H> {
+    /// Fails unless the file holds a filter built with `hasher`'s hash function.
+    pub fn open_with_hasher(path: impl AsRef<Path>, hasher: H) -> Result<Self, SerializationError> {
+        let file = File::open(path)?;
+        // SAFETY: read-only mapping; writers only ever set bits, which `contains` reads
+        // atomically. Truncation is documented on the type.
+        let map = unsafe { Mmap::map(&file)? };
+        let layout = Layout::read(&map, hasher)?;
+        Ok(Self { map, layout })
+    }
+
+    pub fn bit_count(&self) -> usize {
+        self.layout.bit_count
+    }
+
+    pub fn hash_count(&self) -> usize {
+        self.layout.hash_count
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.layout.hash_algorithm
+    }
+
+    /// The mapped bytes as atomics, since a writer in another process may set bits under us.
+    fn bytes(&self) -> &[AtomicU8] {
+        // SAFETY: `AtomicU8` has the size and alignment of `u8`, and the mapping lives
+        // as long as `self`.
+        unsafe { &*(&self.map[..] as *const [u8] as *const [AtomicU8]) }
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for MmapBloomFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        let bytes = self.bytes();
+        self.layout.indices(key.as_ref()).all(|index| {
+            let (byte, mask) = locate(index);
+            bytes[byte].load(Ordering::Relaxed) & mask != 0
+        })
+    }
+}
+
+/// Writable bloom filter whose bits live in a memory-mapped file, see `MmapBloomFilter`
+/// for the readers.
+///
+/// Only one writer may have a file open at a time, enforced with an exclusive lock on it.
+/// Inserts go to the page cache and reach the disk when the OS writes them back or on
+/// `flush`.
+#[derive(Debug)]
+pub struct MmapBloomFilterMut<H: KeyHasher = SeaHash> {
+    map: MmapMut,
+    layout: Layout<H>,
+    // holds the writer lock
+    _file: File,
+}
+
+impl MmapBloomFilterMut {
+    /// Creates a new file sized like `BloomFilterProd::new`, failing if `path` exists.
+    pub fn create(
+        path: impl AsRef<Path>,
+        elements: usize,
+        false_probability: f32,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Self, SerializationError> {
+        Self::create_with_hasher(path, elements, false_probability, hash_algorithm, SeaHash)
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SerializationError> {
+        Self::open_with_hasher(path, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> MmapBloomFilterMut<H> {
+    pub fn create_with_hasher(
+        path: impl AsRef<Path>,
+        elements: usize,
+        false_probability: f32,
+        hash_algorithm: HashAlgorithm,
+        hasher: H,
+    ) -> Result<Self, SerializationError> {
+        assert!(
+            BloomFilterProd::supports(hash_algorithm),
+            "{hash_algorithm:?} does not derive bit indices"
+        );
+        let (bit_count, hash_count) =
+            BloomFilterProd::optimal_parameters(elements, false_probability);
+
+        Self::create_with_layout(
+            path,
+            Layout {
+                bit_count,
+                hash_count,
+                hash_algorithm,
+                hasher,
+            },
+        )
+    }
+
+    /// Writes `filter` to a new file at `path`, e.g. to stop loading a large serialized
+    /// filter into memory on every start.
+    pub fn create_from(
+        path: impl AsRef<Path>,
+        filter: &BloomFilterProd<H>,
+    ) -> Result<Self, SerializationError> {
+        let mut created = Self::create_with_layout(
+            path,
+            Layout {
+                bit_count: filter.bit_count(),
+                hash_count: filter.hash_count(),
+                hash_algorithm: filter.hash_algorithm(),
+                hasher: filter.hasher().clone(),
+            },
+        )?;
+
+        let words = created.map[DATA_OFFSET..].chunks_exact_mut(8);
+        for (word, bits) in words.zip(filter.bits().chunks(64)) {
+            word.copy_from_slice(&bits.load_le::<u64>().to_le_bytes());
+        }
+        Ok(created)
+    }
+
+    /// Fails unless the file holds a filter built with `hasher`'s hash function, or if
+    /// another writer has it open.
+    pub fn open_with_hasher(path: impl AsRef<Path>, hasher: H) -> Result<Self, SerializationError> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        lock(&file)?;
+        // SAFETY: the lock keeps out other writers, readers only map the file read-only.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let layout = Layout::read(&map, hasher)?;
+        Ok(Self {
+            map,
+            layout,
+            _file: file,
+        })
+    }
+
+    /// Blocks until every insert so far is on disk.
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    pub fn bit_count(&self) -> usize {
+        self.layout.bit_count
+    }
+
+    pub fn hash_count(&self) -> usize {
+        self.layout.hash_count
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.layout.hash_algorithm
+    }
+
+    fn create_with_layout(
+        path: impl AsRef<Path>,
+        layout: Layout<H>,
+    ) -> Result<Self, SerializationError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        lock(&file)?;
+        // sparse on most file systems, pages are only allocated once a bit in them is set
+        file.set_len(layout.file_len() as u64)?;
+
+        // SAFETY: the file was just created and is locked against other writers.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        layout.write_header(&mut map)?;
+        map.flush()?;
+
+        Ok(Self {
+            map,
+            layout,
+            _file: file,
+        })
+    }
+}
+
+fn lock(file: &File) -> io::Result<()> {
+    file.try_lock().map_err(|err| match err {
+        TryLockError::WouldBlock => io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "another writer has the filter open",
+        ),
+        TryLockError::Error(err) => err,
+    })
+}
+
+impl<H: KeyHasher> BloomFilter for MmapBloomFilterMut<H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        for index in self.layout.indices(key.as_ref()) {
+            let (byte, mask) = locate(index);
+            self.map[byte] |= mask;
+        }
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for MmapBloomFilterMut<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.layout.indices(key.as_ref()).all(|index| {
+            let (byte, mask) = locate(index);
+            self.map[byte] & mask != 0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::{Fnv1a, SipHash24};
+    use crate::serialization::HashFunction;
+
+    const FRUIT: [&str; 4] = ["mango", "apple", "orange", "banana"];
+
+    #[test]
+    fn test_insert_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fruit.bfm");
+
+        let mut writer =
+            MmapBloomFilterMut::create(&path, 1000, 0.01, HashAlgorithm::Double).unwrap();
+        FRUIT.iter().for_each(|key| writer.insert(key));
+        assert!(writer.contains("mango"));
+        writer.flush().unwrap();
+        drop(writer);
+
+        let reader = MmapBloomFilter::open(&path).unwrap();
+        assert!(FRUIT.iter().all(|key| reader.contains(key)));
+        assert!(!reader.contains("carrot"));
+        assert_eq!(reader.hash_algorithm(), HashAlgorithm::Double);
+        assert_eq!(
+            (reader.bit_count(), reader.hash_count()),
+            BloomFilterProd::optimal_parameters(1000, 0.01)
+        );
+
+        let mut writer = MmapBloomFilterMut::open(&path).unwrap();
+        writer.insert("carrot");
+        assert!(writer.contains("mango") && writer.contains("carrot"));
+    }
+
+    #[test]
+    fn test_readers_see_inserts_without_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fruit.bfm");
+
+        let mut writer =
+            MmapBloomFilterMut::create(&path, 100, 0.01, HashAlgorithm::Seeded).unwrap();
+        let readers = [
+            MmapBloomFilter::open(&path).unwrap(),
+            MmapBloomFilter::open(&path).unwrap(),
+        ];
+        assert!(!readers[0].contains("mango"));
+
+        writer.insert("mango");
+        assert!(readers.iter().all(|reader| reader.contains("mango")));
+    }
+
+    #[test]
+    fn test_single_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fruit.bfm");
+
+        let writer = MmapBloomFilterMut::create(&path, 100, 0.01, HashAlgorithm::Seeded).unwrap();
+        let Err(SerializationError::Io(err)) = MmapBloomFilterMut::open(&path) else {
+            panic!("second writer opened the file");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        drop(writer);
+        assert!(MmapBloomFilterMut::open(&path).is_ok());
+    }
+
+    #[test]
+    fn test_create_refuses_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fruit.bfm");
+        std::fs::write(&path, "precious").unwrap();
+
+        assert!(MmapBloomFilterMut::create(&path, 100, 0.01, HashAlgorithm::Seeded).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"precious");
+    }
+
+    #[test]
+    fn test_create_from_matches_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("numbers.bfm");
+
+        let mut bl = BloomFilterProd::with_hash_algorithm(1000, 0.01, HashAlgorithm::Double);
+        (0..1000).for_each(|i| bl.insert(&format!("key-{i}")));
+        MmapBloomFilterMut::create_from(&path, &bl).unwrap();
+
+        let mapped = MmapBloomFilter::open(&path).unwrap();
+        for i in 0..5000 {
+            let key = format!("key-{i}");
+            assert_eq!(mapped.contains(&key), bl.contains(&key), "{key}");
+        }
+    }
+
+    #[test]
+    fn test_hasher_checked_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keyed.bfm");
+        let sip = SipHash24::new([3; 16]);
+
+        let mut writer = MmapBloomFilterMut::create_with_hasher(
+            &path,
+            100,
+            0.01,
+            HashAlgorithm::Seeded,
+            sip.clone(),
+        )
+        .unwrap();
+        writer.insert("mango");
+        drop(writer);
+
+        assert!(matches!(
+            MmapBloomFilter::open(&path),
+            Err(SerializationError::HashFunctionMismatch {
+                expected: HashFunction::SeaHash,
+                found: HashFunction::SipHash24,
+            })
+        ));
+        assert!(MmapBloomFilter::open_with_hasher(&path, Fnv1a).is_err());
+        assert!(MmapBloomFilter::open_with_hasher(&path, sip)
+            .unwrap()
+            .contains("mango"));
+    }
+
+    #[test]
+    fn test_reject_bad_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fruit.bfm");
+        MmapBloomFilterMut::create(&path, 1000, 0.01, HashAlgorithm::Seeded).unwrap();
+
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(DATA_OFFSET as u64 + 8).unwrap();
+        assert!(matches!(
+            MmapBloomFilter::open(&path),
+            Err(SerializationError::InvalidParameters(_))
+        ));
+
+        std::fs::write(&path, "not a filter").unwrap();
+        assert!(MmapBloomFilter::open(&path).is_err());
+        std::fs::write(&path, "").unwrap();
+        assert!(MmapBloomFilter::open(&path).is_err());
+    }
+}
@@ -1,2 +1,15 @@
+pub mod atomic_bloom_filter;
+pub mod binary_fuse_filter;
+pub mod blocked_bloom_filter;
 pub mod bloom_filter_32_arr;
-pub mod bloom_filter_prod;
\ No newline at end of file
+pub mod bloom_filter_builder;
+pub mod bloom_filter_n;
+pub mod bloom_filter_prod;
+pub mod counting_bloom_filter;
+pub mod cuckoo_filter;
+pub mod invertible_bloom_lookup_table;
+pub mod keyed_bloom_filter;
+pub mod mmap_bloom_filter;
+pub mod rotating_bloom_filter;
+pub mod scalable_bloom_filter;
+pub mod stable_bloom_filter;
@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::HashAlgorithm;
+
+/// Time source for `RotatingBloomFilter`, a monotonic offset from an arbitrary start.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// Wall time since the clock was created.
+#[derive(Debug, Clone)]
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Only moves when told to. Clones share the same time, so a test can keep one
+/// and hand the other to the filter.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn advance(&self, by: Duration) {
+        self.nanos
+            .fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// Answers "seen in the last `window`?" with a ring of `BloomFilterProd` generations.
+///
+/// Inserts go to the newest generation, lookups check every live one. Every
+/// `window / (generations - 1)` the oldest generation is cleared and reused as the newest,
+/// so a key is remembered for at least `window` and at most `window * generations / (generations - 1)`.
+///
+/// Each generation is sized for `elements` keys at `false_probability`; since a lookup checks
+/// all of them the overall false positive rate is up to `generations * false_probability`.
+#[derive(Debug)]
+pub struct RotatingBloomFilter<C: Clock = MonotonicClock, H: KeyHasher = SeaHash> {
+    /// Newest first.
+    generations: VecDeque<BloomFilterProd<H>>,
+    generation_length: Duration,
+    generation_started: Duration,
+    clock: C,
+}
+
+impl RotatingBloomFilter<MonotonicClock> {
+    pub fn new(
+        elements: usize,
+        false_probability: f32,
+        window: Duration,
+        generations: usize,
+    ) -> Self {
+        Self::with_clock(
+            elements,
+            false_probability,
+            window,
+            generations,
+            MonotonicClock::default(),
+        )
+    }
+}
+
+impl<C: Clock> RotatingBloomFilter<C> {
+    pub fn with_clock(
+        elements: usize,
+        false_probability: f32,
+        window: Duration,
+        generations: usize,
+        clock: C,
+    ) -> Self {
+        Self::with_hasher(
+            elements,
+            false_probability,
+            window,
+            generations,
+            clock,
+            SeaHash,
+        )
+    }
+}
+
+impl<C: Clock, H: KeyHasher> RotatingBloomFilter<C, H> {
+    pub fn with_hasher(
+        elements: usize,
+        false_probability: f32,
+        window: Duration,
+        generations: usize,
+        clock: C,
+        hasher: H,
+    ) -> Self {
+        assert!(generations >= 2, "need at least two generations");
+        let generation_length = window / (generations as u32 - 1);
+        assert!(!generation_length.is_zero(), "window is too short");
+
+        Self {
+            generations: (0..generations)
+                .map(|_| {
+                    BloomFilterProd::with_hasher(
+                        elements,
+                        false_probability,
+                        HashAlgorithm::Seeded,
+                        hasher.clone(),
+                    )
+                })
+                .collect(),
+            generation_length,
+            generation_started: clock.now(),
+            clock,
+        }
+    }
+
+    /// Drops the oldest generation and starts a fresh one, restarting the clock-driven schedule.
+    pub fn rotate(&mut self) {
+        self.shift();
+        self.generation_started = self.clock.now();
+    }
+
+    /// Applies the rotations the clock says are due. `insert` calls this itself.
+    pub fn tick(&mut self) {
+        let due = self.due_rotations();
+        if due >= self.generations.len() {
+            // every generation expired, restart the schedule instead of catching up on it
+            (0..self.generations.len()).for_each(|_| self.shift());
+            self.generation_started = self.clock.now();
+        } else {
+            (0..due).for_each(|_| {
+                self.shift();
+                self.generation_started += self.generation_length;
+            });
+        }
+    }
+
+    pub fn generation_count(&self) -> usize {
+        self.generations.len()
+    }
+
+    fn due_rotations(&self) -> usize {
+        let elapsed = self.clock.now().saturating_sub(self.generation_started);
+        let due = elapsed.as_nanos() / self.generation_length.as_nanos();
+        usize::try_from(due).unwrap_or(usize::MAX)
+    }
+
+    fn shift(&mut self) {
+        let mut oldest = self
+            .generations
+            .pop_back()
+            .expect("there are always generations");
+        oldest.clear();
+        self.generations.push_front(oldest);
+    }
+}
+
+impl<C: Clock, H: KeyHasher> BloomFilter for RotatingBloomFilter<C, H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        self.tick();
+        self.generations
+            .front_mut()
+            .expect("there are always generations")
+            .insert(key);
+    }
+}
+
+impl<C: Clock, H: KeyHasher> ReadableBloomFilter for RotatingBloomFilter<C, H> {
+    /// Generations that expired since the last `tick` are skipped, so lookups are
+    /// correct even when nothing has been inserted for a while.
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        let live = self.generations.len().saturating_sub(self.due_rotations());
+
+        self.generations
+            .iter()
+            .take(live)
+            .any(|generation| generation.contains(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn filter(clock: &ManualClock) -> RotatingBloomFilter<ManualClock> {
+        // 4 generations of 2 minutes each
+        RotatingBloomFilter::with_clock(1000, 0.01, 6 * MINUTE, 4, clock.clone())
+    }
+
+    #[test]
+    fn test_manual_rotation() {
+        let clock = ManualClock::default();
+        let mut rf = filter(&clock);
+        rf.insert("request-1");
+
+        (0..3).for_each(|_| rf.rotate());
+        assert!(rf.contains("request-1"));
+
+        rf.rotate();
+        assert!(!rf.contains("request-1"));
+    }
+
+    #[test]
+    fn test_remembered_for_the_window() {
+        let clock = ManualClock::default();
+        let mut rf = filter(&clock);
+        rf.insert("request-1");
+
+        clock.advance(6 * MINUTE);
+        rf.insert("request-2");
+        assert!(rf.contains("request-1"));
+
+        clock.advance(2 * MINUTE);
+        rf.insert("request-3");
+        assert!(!rf.contains("request-1"));
+        assert!(rf.contains("request-2"));
+    }
+
+    #[test]
+    fn test_lookup_skips_expired_generations_without_insert() {
+        let clock = ManualClock::default();
+        let mut rf = filter(&clock);
+        rf.insert("request-1");
+
+        clock.advance(7 * MINUTE);
+        assert!(rf.contains("request-1"));
+        clock.advance(MINUTE);
+        assert!(!rf.contains("request-1"));
+    }
+
+    #[test]
+    fn test_long_pause_clears_everything() {
+        let clock = ManualClock::default();
+        let mut rf = filter(&clock);
+        rf.insert("request-1");
+
+        clock.advance(60 * MINUTE);
+        rf.tick();
+        assert!(!rf.contains("request-1"));
+        assert_eq!(rf.generation_count(), 4);
+
+        rf.insert("request-2");
+        assert!(rf.contains("request-2"));
+    }
+
+    #[test]
+    fn test_pause_longer_than_u32_generations() {
+        let clock = ManualClock::default();
+        // 4 generations of a millisecond each
+        let mut rf =
+            RotatingBloomFilter::with_clock(1000, 0.01, Duration::from_millis(3), 4, clock.clone());
+
+        clock.advance(Duration::from_millis(u32::MAX as u64 + 2));
+        rf.insert("request-1");
+        clock.advance(Duration::from_micros(500));
+        assert!(rf.contains("request-1"));
+    }
+
+    #[test]
+    fn test_schedule_does_not_drift() {
+        let clock = ManualClock::default();
+        let mut rf = filter(&clock);
+
+        clock.advance(3 * MINUTE);
+        rf.tick(); // one rotation, the next is due at 4 minutes
+        rf.insert("request-1");
+
+        clock.advance(MINUTE);
+        rf.tick();
+        (0..2).for_each(|_| {
+            clock.advance(2 * MINUTE);
+            rf.tick();
+        });
+        assert!(rf.contains("request-1"));
+
+        clock.advance(2 * MINUTE);
+        assert!(!rf.contains("request-1"));
+    }
+}
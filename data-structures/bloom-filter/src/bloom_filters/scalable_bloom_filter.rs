@@ -0,0 +1,220 @@
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::HashAlgorithm;
+
+const DEFAULT_GROWTH_FACTOR: usize = 2;
+const DEFAULT_TIGHTENING_RATIO: f32 = 0.85;
+
+#[derive(Debug)]
+struct Stage<H: KeyHasher> {
+    filter: BloomFilterProd<H>,
+    capacity: usize,
+    len: usize,
+}
+
+impl<H: KeyHasher> Stage<H> {
+    fn new(capacity: usize, false_probability: f32, hasher: H) -> Self {
+        Self {
+            filter: BloomFilterProd::with_hasher(
+                capacity,
+                false_probability,
+                HashAlgorithm::Seeded,
+                hasher,
+            ),
+            capacity,
+            len: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.len >= self.capacity
+    }
+}
+
+/// Scalable bloom filter (Almeida et al.): a chain of `BloomFilterProd` stages.
+///
+/// Stage `i` holds `initial_capacity * growth_factor^i` keys at a false positive rate of
+/// `p * (1 - r) * r^i`, where `r` is the tightening ratio. The series sums to `p`,
+/// so the overall false positive rate stays below `p` however many keys arrive.
+#[derive(Debug)]
+pub struct ScalableBloomFilter<H: KeyHasher = SeaHash> {
+    stages: Vec<Stage<H>>,
+    false_probability: f32,
+    growth_factor: usize,
+    tightening_ratio: f32,
+    hasher: H,
+}
+
+impl ScalableBloomFilter {
+    /// Doubles the capacity of every new stage and tightens its error by 0.85.
+    pub fn new(initial_capacity: usize, false_probability: f32) -> Self {
+        Self::with_growth(
+            initial_capacity,
+            false_probability,
+            DEFAULT_GROWTH_FACTOR,
+            DEFAULT_TIGHTENING_RATIO,
+        )
+    }
+
+    pub fn with_growth(
+        initial_capacity: usize,
+        false_probability: f32,
+        growth_factor: usize,
+        tightening_ratio: f32,
+    ) -> Self {
+        Self::with_hasher(
+            initial_capacity,
+            false_probability,
+            growth_factor,
+            tightening_ratio,
+            SeaHash,
+        )
+    }
+}
+
+impl<H: KeyHasher> ScalableBloomFilter<H> {
+    pub fn with_hasher(
+        initial_capacity: usize,
+        false_probability: f32,
+        growth_factor: usize,
+        tightening_ratio: f32,
+        hasher: H,
+    ) -> Self {
+        assert!(initial_capacity > 0, "initial capacity must be positive");
+        assert!(growth_factor >= 1, "growth factor must be at least 1");
+        assert!(
+            tightening_ratio > 0.0 && tightening_ratio < 1.0,
+            "tightening ratio must be in (0, 1)"
+        );
+
+        let mut filter = Self {
+            stages: Vec::new(),
+            false_probability,
+            growth_factor,
+            tightening_ratio,
+            hasher,
+        };
+        filter.stages.push(Stage::new(
+            initial_capacity,
+            filter.stage_false_probability(0),
+            filter.hasher.clone(),
+        ));
+        filter
+    }
+
+    pub fn stage_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Number of distinct keys added, keys that already looked present are not counted.
+    pub fn len(&self) -> usize {
+        self.stages.iter().map(|stage| stage.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn stage_false_probability(&self, stage: usize) -> f32 {
+        self.false_probability
+            * (1.0 - self.tightening_ratio)
+            * self.tightening_ratio.powi(stage as i32)
+    }
+
+    fn current_stage(&self) -> &Stage<H> {
+        self.stages.last().expect("there is always one stage")
+    }
+
+    fn add_stage(&mut self) {
+        let capacity = self.current_stage().capacity * self.growth_factor;
+        let false_probability = self.stage_false_probability(self.stages.len());
+
+        self.stages
+            .push(Stage::new(capacity, false_probability, self.hasher.clone()));
+    }
+}
+
+impl<H: KeyHasher> BloomFilter for ScalableBloomFilter<H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        // re-inserting a known key would only eat into the stage capacity
+        if self.contains(key) {
+            return;
+        }
+
+        if self.current_stage().is_full() {
+            self.add_stage();
+        }
+
+        let stage = self.stages.last_mut().expect("there is always one stage");
+        stage.filter.insert(key);
+        stage.len += 1;
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for ScalableBloomFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.stages.iter().any(|stage| stage.filter.contains(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starts_with_one_stage() {
+        let bl = ScalableBloomFilter::new(10, 0.01);
+        assert_eq!(bl.stage_count(), 1);
+        assert!(bl.is_empty());
+    }
+
+    #[test]
+    fn test_grows_past_capacity() {
+        let mut bl = ScalableBloomFilter::new(10, 0.01);
+        (0..100).for_each(|i| bl.insert(&format!("key-{i}")));
+
+        // 10 + 20 + 40 < 100 <= 10 + 20 + 40 + 80
+        assert_eq!(bl.stage_count(), 4);
+        assert!((0..100).all(|i| bl.contains(&format!("key-{i}"))));
+    }
+
+    #[test]
+    fn test_duplicates_do_not_grow() {
+        let mut bl = ScalableBloomFilter::new(10, 0.01);
+        (0..100).for_each(|_| bl.insert("mango"));
+
+        assert_eq!(bl.len(), 1);
+        assert_eq!(bl.stage_count(), 1);
+    }
+
+    #[test]
+    fn test_custom_growth() {
+        let mut bl = ScalableBloomFilter::with_growth(10, 0.01, 4, 0.5);
+        (0..100).for_each(|i| bl.insert(&format!("key-{i}")));
+
+        // 10 + 40 < 100 <= 10 + 40 + 160
+        assert_eq!(bl.stage_count(), 3);
+    }
+
+    #[test]
+    fn test_stage_errors_tighten() {
+        let bl = ScalableBloomFilter::with_growth(10, 0.01, 2, 0.5);
+
+        assert!((bl.stage_false_probability(0) - 0.005).abs() < 1e-6);
+        assert!((bl.stage_false_probability(1) - 0.0025).abs() < 1e-6);
+        let bound: f32 = (0..32).map(|i| bl.stage_false_probability(i)).sum();
+        assert!(bound <= 0.01);
+    }
+
+    #[test]
+    fn test_false_positive_rate_holds_when_overfilled() {
+        let mut bl = ScalableBloomFilter::new(100, 0.01);
+        (0..10_000).for_each(|i| bl.insert(&format!("key-{i}")));
+
+        let false_positives = (0..10_000)
+            .filter(|i| bl.contains(&format!("other-{i}")))
+            .count();
+        assert!(false_positives < 200, "{false_positives} false positives");
+    }
+}
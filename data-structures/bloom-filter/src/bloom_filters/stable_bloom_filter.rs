@@ -0,0 +1,268 @@
+use bitvec::prelude::*;
+
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_prod::Indices;
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::rng::SplitMix64;
+use crate::serialization::HashAlgorithm;
+
+/// Stable bloom filter (Deng & Rafiei) for deduplicating unbounded streams.
+///
+/// Every cell is a small counter. An insert first decrements `decrement_count` cells starting
+/// at a random position, then sets the key's `hash_count` cells to the maximum. Old keys decay
+/// out, so the fraction of zero cells settles at a fixed point instead of going to zero.
+///
+/// The trade-off:
+/// - false positives are bounded forever by `stable_false_positive_rate`, whatever the
+///   length of the stream;
+/// - false negatives appear instead: a key is forgotten once enough later inserts have
+///   decayed one of its cells to zero. A larger `decrement_count` forgets faster and lowers
+///   the false positive rate, a smaller one remembers longer at a higher rate.
+#[derive(Debug, Clone)]
+pub struct StableBloomFilter<H: KeyHasher = SeaHash> {
+    cells: BitVec,
+    cell_bits: usize,
+    cell_count: usize,
+    hash_count: usize,
+    decrement_count: usize,
+    rng: SplitMix64,
+    hasher: H,
+}
+
+impl StableBloomFilter {
+    /// Picks the decay rate so the false positive rate settles at `false_probability`.
+    pub fn new(
+        cell_count: usize,
+        cell_bits: usize,
+        hash_count: usize,
+        false_probability: f32,
+        seed: u64,
+    ) -> Self {
+        let decrement_count =
+            Self::decrement_count_for(cell_count, cell_bits, hash_count, false_probability);
+
+        Self::with_decay(cell_count, cell_bits, hash_count, decrement_count, seed)
+    }
+
+    /// `decrement_count` cells are decremented per insert. `seed` makes the decay reproducible.
+    pub fn with_decay(
+        cell_count: usize,
+        cell_bits: usize,
+        hash_count: usize,
+        decrement_count: usize,
+        seed: u64,
+    ) -> Self {
+        Self::with_hasher(
+            cell_count,
+            cell_bits,
+            hash_count,
+            decrement_count,
+            seed,
+            SeaHash,
+        )
+    }
+
+    /// Inverts `stable_false_positive_rate` for the decrement count, rounding up.
+    pub fn decrement_count_for(
+        cell_count: usize,
+        cell_bits: usize,
+        hash_count: usize,
+        false_probability: f32,
+    ) -> usize {
+        let max = ((1u16 << cell_bits) - 1) as f64;
+        let k = hash_count as f64;
+        let spread = 1.0 / k - 1.0 / cell_count as f64;
+
+        let zeros = 1.0 - (false_probability as f64).powf(1.0 / k);
+        let decrement_count = 1.0 / (spread * (zeros.powf(-1.0 / max) - 1.0));
+
+        (decrement_count.ceil() as usize).clamp(1, cell_count)
+    }
+}
+
+impl<H: KeyHasher> StableBloomFilter<H> {
+    /// Like `with_decay`, hashing keys with `hasher`.
+    pub fn with_hasher(
+        cell_count: usize,
+        cell_bits: usize,
+        hash_count: usize,
+        decrement_count: usize,
+        seed: u64,
+        hasher: H,
+    ) -> Self {
+        assert!(
+            (1..=8).contains(&cell_bits),
+            "cells must be between 1 and 8 bits"
+        );
+        assert!(
+            hash_count > 0 && hash_count <= cell_count,
+            "need between 1 and cell_count hash functions"
+        );
+
+        Self {
+            cells: bitvec![0; cell_count * cell_bits],
+            cell_bits,
+            cell_count,
+            hash_count,
+            decrement_count: decrement_count.clamp(1, cell_count),
+            rng: SplitMix64::new(seed),
+            hasher,
+        }
+    }
+
+    /// False positive rate the filter converges to, independent of how many keys arrive:
+    /// `(1 - (1 / (1 + 1 / (P * (1/k - 1/m))))^Max)^k`.
+    pub fn stable_false_positive_rate(&self) -> f64 {
+        let k = self.hash_count as f64;
+        let spread = 1.0 / k - 1.0 / self.cell_count as f64;
+        let zeros = (1.0 / (1.0 + 1.0 / (self.decrement_count as f64 * spread)))
+            .powf(self.max_value() as f64);
+
+        (1.0 - zeros).powf(k)
+    }
+
+    pub fn decrement_count(&self) -> usize {
+        self.decrement_count
+    }
+
+    /// Fraction of cells at zero, converges as the stream goes on.
+    pub fn zero_ratio(&self) -> f64 {
+        let zeros = (0..self.cell_count)
+            .filter(|&cell| self.cell(cell) == 0)
+            .count();
+        zeros as f64 / self.cell_count as f64
+    }
+
+    fn max_value(&self) -> u8 {
+        ((1u16 << self.cell_bits) - 1) as u8
+    }
+
+    fn indices<'a>(&self, key: &'a [u8]) -> Indices<'a, H> {
+        Indices::new(
+            key,
+            self.hasher.clone(),
+            HashAlgorithm::Seeded,
+            self.hash_count,
+            self.cell_count,
+        )
+    }
+
+    fn cell(&self, cell: usize) -> u8 {
+        let start = cell * self.cell_bits;
+        self.cells[start..start + self.cell_bits].load::<u8>()
+    }
+
+    fn set_cell(&mut self, cell: usize, value: u8) {
+        let start = cell * self.cell_bits;
+        self.cells[start..start + self.cell_bits].store::<u8>(value);
+    }
+
+    /// Decrements a run of consecutive cells from a random start. Deng & Rafiei decrement
+    /// `P` randomly chosen cells instead; the run is a locality optimization that departs
+    /// from the paper.
+    fn decay(&mut self) {
+        let start = self.rng.below(self.cell_count);
+        for offset in 0..self.decrement_count {
+            let cell = (start + offset) % self.cell_count;
+            let value = self.cell(cell);
+            if value > 0 {
+                self.set_cell(cell, value - 1);
+            }
+        }
+    }
+}
+
+impl<H: KeyHasher> BloomFilter for StableBloomFilter<H> {
+    fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        self.decay();
+
+        let max = self.max_value();
+        for cell in self.indices(key.as_ref()) {
+            self.set_cell(cell, max);
+        }
+    }
+}
+
+impl<H: KeyHasher> ReadableBloomFilter for StableBloomFilter<H> {
+    fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.indices(key.as_ref()).all(|cell| self.cell(cell) > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_zeros() {
+        let sbf = StableBloomFilter::with_decay(1000, 3, 3, 10, 0);
+        assert_eq!(sbf.zero_ratio(), 1.0);
+    }
+
+    #[test]
+    fn test_latest_key_is_present() {
+        let mut sbf = StableBloomFilter::with_decay(1000, 3, 3, 10, 0);
+        for i in 0..10_000 {
+            let key = format!("key-{i}");
+            sbf.insert(&key);
+            assert!(sbf.contains(&key));
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_state() {
+        let mut a = StableBloomFilter::with_decay(1000, 3, 3, 10, 42);
+        let mut b = StableBloomFilter::with_decay(1000, 3, 3, 10, 42);
+        (0..5_000).for_each(|i| {
+            a.insert(&format!("key-{i}"));
+            b.insert(&format!("key-{i}"));
+        });
+
+        assert_eq!(a.cells, b.cells);
+    }
+
+    #[test]
+    fn test_decrement_count_round_trip() {
+        let decrement_count = StableBloomFilter::decrement_count_for(10_000, 3, 3, 0.02);
+        let sbf = StableBloomFilter::with_decay(10_000, 3, 3, decrement_count, 0);
+
+        assert!(sbf.stable_false_positive_rate() <= 0.02);
+        let looser = StableBloomFilter::with_decay(10_000, 3, 3, decrement_count - 1, 0);
+        assert!(looser.stable_false_positive_rate() > 0.02);
+    }
+
+    #[test]
+    fn test_false_positive_rate_stays_bounded() {
+        let mut sbf = StableBloomFilter::new(10_000, 3, 3, 0.02, 7);
+        (0..30_000).for_each(|i| sbf.insert(&format!("key-{i}")));
+
+        let false_positives = (0..50_000)
+            .filter(|i| sbf.contains(&format!("probe-{i}")))
+            .count();
+        let rate = false_positives as f64 / 50_000.0;
+        assert!(rate < 0.03, "{rate}");
+    }
+
+    #[test]
+    fn test_old_keys_decay() {
+        let mut sbf = StableBloomFilter::new(10_000, 3, 3, 0.02, 7);
+        (0..1_000).for_each(|i| sbf.insert(&format!("old-{i}")));
+        (0..30_000).for_each(|i| sbf.insert(&format!("key-{i}")));
+
+        let remembered = (0..1_000)
+            .filter(|i| sbf.contains(&format!("old-{i}")))
+            .count();
+        assert!(remembered < 100, "{remembered} old keys still present");
+    }
+
+    #[test]
+    fn test_zero_ratio_settles() {
+        let mut sbf = StableBloomFilter::new(10_000, 3, 3, 0.02, 7);
+        (0..20_000).for_each(|i| sbf.insert(&format!("key-{i}")));
+        let settled = sbf.zero_ratio();
+        (20_000..40_000).for_each(|i| sbf.insert(&format!("key-{i}")));
+
+        assert!((sbf.zero_ratio() - settled).abs() < 0.02);
+        assert!(settled > 0.5);
+    }
+}
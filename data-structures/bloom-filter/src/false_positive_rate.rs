@@ -0,0 +1,134 @@
+use crate::bloom_filter::BloomFilter;
+use crate::rng::SplitMix64;
+
+const INSERTED: u8 = 0;
+const PROBE: u8 = 1;
+
+/// Outcome of `measure`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FalsePositiveReport {
+    pub inserted: usize,
+    /// Inserted keys the filter did not find afterwards, should always be 0.
+    pub false_negatives: usize,
+    pub probes: usize,
+    pub false_positives: usize,
+}
+
+impl FalsePositiveReport {
+    pub fn rate(&self) -> f64 {
+        self.false_positives as f64 / self.probes as f64
+    }
+
+    /// No false negatives and a rate of at most `target * (1 + tolerance)`.
+    pub fn is_within(&self, target: f64, tolerance: f64) -> bool {
+        self.false_negatives == 0 && self.rate() <= target * (1.0 + tolerance)
+    }
+}
+
+/// Pools reports, e.g. of many small filters whose individual rates are too noisy to judge.
+impl std::iter::Sum for FalsePositiveReport {
+    fn sum<I: Iterator<Item = Self>>(reports: I) -> Self {
+        reports.fold(
+            Self {
+                inserted: 0,
+                false_negatives: 0,
+                probes: 0,
+                false_positives: 0,
+            },
+            |total, report| Self {
+                inserted: total.inserted + report.inserted,
+                false_negatives: total.false_negatives + report.false_negatives,
+                probes: total.probes + report.probes,
+                false_positives: total.false_positives + report.false_positives,
+            },
+        )
+    }
+}
+
+impl std::fmt::Display for FalsePositiveReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} keys, {} false negatives, {}/{} false positives ({:.5})",
+            self.inserted,
+            self.false_negatives,
+            self.false_positives,
+            self.probes,
+            self.rate()
+        )
+    }
+}
+
+/// Inserts `elements` random keys into an empty `filter`, checks they are all found, then
+/// counts how many of `probes` other random keys it claims to contain.
+///
+/// Keys are 9 bytes: 8 random ones from `seed` and a tag byte that keeps the inserted and
+/// probed keys disjoint.
+pub fn measure<F: BloomFilter>(
+    filter: &mut F,
+    elements: usize,
+    probes: usize,
+    seed: u64,
+) -> FalsePositiveReport {
+    let mut rng = SplitMix64::new(seed);
+    let mut key = |tag: u8| {
+        let mut key = [tag; 9];
+        key[..8].copy_from_slice(&rng.next_u64().to_le_bytes());
+        key
+    };
+
+    let inserted = (0..elements).map(|_| key(INSERTED)).collect::<Vec<_>>();
+    inserted.iter().for_each(|key| filter.insert(key));
+    let false_negatives = inserted.iter().filter(|key| !filter.contains(*key)).count();
+
+    let false_positives = (0..probes).filter(|_| filter.contains(&key(PROBE))).count();
+
+    FalsePositiveReport {
+        inserted: elements,
+        false_negatives,
+        probes,
+        false_positives,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+
+    #[test]
+    fn test_empty_filter_has_no_false_positives() {
+        let mut bl = BloomFilterProd::new(100, 0.01);
+        let report = measure(&mut bl, 0, 1000, 0);
+
+        assert_eq!(report.false_positives, 0);
+        assert!(report.is_within(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_sum_pools_counts() {
+        let total: FalsePositiveReport = (0..4)
+            .map(|seed| measure(&mut BloomFilterProd::new(100, 0.01), 100, 1000, seed))
+            .sum();
+
+        assert_eq!(total.inserted, 400);
+        assert_eq!(total.probes, 4000);
+    }
+
+    #[test]
+    fn test_is_within() {
+        let report = FalsePositiveReport {
+            inserted: 10,
+            false_negatives: 0,
+            probes: 1000,
+            false_positives: 12,
+        };
+        assert!(report.is_within(0.01, 0.25));
+        assert!(!report.is_within(0.01, 0.1));
+        assert!(!FalsePositiveReport {
+            false_negatives: 1,
+            ..report
+        }
+        .is_within(0.01, 0.25));
+    }
+}
@@ -0,0 +1,336 @@
+use std::hash::Hasher;
+
+use siphasher::sip::SipHasher24;
+
+use crate::serialization::HashFunction;
+
+/// Hashes keys for the filters, which are generic over it and default to `SeaHash`.
+///
+/// Serialized filters depend on the exact output, so implementations must give the same
+/// hashes on every run and platform. `FUNCTION` is recorded next to a serialized filter
+/// and checked again when it is read back.
+pub trait KeyHasher: Clone + PartialEq + std::fmt::Debug {
+    const FUNCTION: HashFunction;
+
+    /// Hash of `key` under `seed`; the filters use seeds `0..hash_count`.
+    fn hash(&self, key: &[u8], seed: u64) -> u64;
+}
+
+/// `seahash::hash_seeded` with the seed as first key, what every filter used before hashers
+/// became pluggable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeaHash;
+
+impl KeyHasher for SeaHash {
+    const FUNCTION: HashFunction = HashFunction::SeaHash;
+
+    fn hash(&self, key: &[u8], seed: u64) -> u64 {
+        seahash::hash_seeded(key, seed, 0, 0, 0)
+    }
+}
+
+/// 64-bit FNV-1a. Seed 0 is the standard hash; other seeds start from the offset basis
+/// xored with the seed's murmur3 finalizer, since FNV spreads a seed fed in as bytes poorly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fnv1a;
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+}
+
+impl KeyHasher for Fnv1a {
+    const FUNCTION: HashFunction = HashFunction::Fnv1a;
+
+    fn hash(&self, key: &[u8], seed: u64) -> u64 {
+        key.iter()
+            .fold(Self::OFFSET_BASIS ^ fmix64(seed), |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(Self::PRIME)
+            })
+    }
+}
+
+/// Lower 64 bits of MurmurHash3 x64 128, the half `mmh3.hash64` returns first.
+/// Murmur3 seeds are 32 bits, the upper half of `seed` is ignored.
+///
+/// Only the hash matches, filters derive their bit indices their own way and can't read
+/// bits set by Guava's `BloomFilter`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Murmur3;
+
+impl KeyHasher for Murmur3 {
+    const FUNCTION: HashFunction = HashFunction::Murmur3;
+
+    fn hash(&self, key: &[u8], seed: u64) -> u64 {
+        murmur3_x64_128(key, seed as u32).0
+    }
+}
+
+/// A `KeyHasher` whose output depends on a secret key, so an attacker who knows the code but
+/// not the key can't pick keys that land on the bits they want.
+///
+/// Filters written with `write_to` leave the key out and are read back with the same hasher;
+/// `KeyedBloomFilter` can also write the key, when the caller asks for it.
+pub trait SecretKeyHasher: KeyHasher {
+    /// Key length in bytes.
+    const KEY_LEN: usize;
+
+    /// `None` unless `key` is `KEY_LEN` bytes long.
+    fn from_key_bytes(key: &[u8]) -> Option<Self>;
+
+    fn key_bytes(&self) -> Vec<u8>;
+}
+
+/// SipHash-2-4 under a secret 128-bit key, for keys an attacker may choose.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SipHash24 {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipHash24 {
+    pub fn new(key: [u8; 16]) -> Self {
+        let (k0, k1) = key.split_at(8);
+        Self {
+            k0: u64::from_le_bytes(k0.try_into().expect("8 bytes")),
+            k1: u64::from_le_bytes(k1.try_into().expect("8 bytes")),
+        }
+    }
+}
+
+impl std::fmt::Debug for SipHash24 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SipHash24 { .. }") // keep the key out of logs
+    }
+}
+
+impl KeyHasher for SipHash24 {
+    const FUNCTION: HashFunction = HashFunction::SipHash24;
+
+    fn hash(&self, key: &[u8], seed: u64) -> u64 {
+        let mut hasher = SipHasher24::new_with_keys(self.k0, self.k1);
+        hasher.write(&seed.to_le_bytes());
+        hasher.write(key);
+        hasher.finish()
+    }
+}
+
+impl SecretKeyHasher for SipHash24 {
+    const KEY_LEN: usize = 16;
+
+    fn from_key_bytes(key: &[u8]) -> Option<Self> {
+        Some(Self::new(key.try_into().ok()?))
+    }
+
+    fn key_bytes(&self) -> Vec<u8> {
+        [self.k0.to_le_bytes(), self.k1.to_le_bytes()].concat()
+    }
+}
+
+/// Two SipHash-2-4 hashes under independent halves of a secret 256-bit key, xored: guessing
+/// one half tells nothing without the other. Twice the cost of `SipHash24`.
+///
+/// The high half hashes under the seed with its top bit flipped, which filter seeds never set,
+/// so a key with equal halves doesn't cancel out.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DoubleSipHash24 {
+    low: SipHash24,
+    high: SipHash24,
+}
+
+impl DoubleSipHash24 {
+    pub fn new(key: [u8; 32]) -> Self {
+        let (low, high) = key.split_at(16);
+        Self {
+            low: SipHash24::new(low.try_into().expect("16 bytes")),
+            high: SipHash24::new(high.try_into().expect("16 bytes")),
+        }
+    }
+}
+
+impl std::fmt::Debug for DoubleSipHash24 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DoubleSipHash24 { .. }")
+    }
+}
+
+impl KeyHasher for DoubleSipHash24 {
+    const FUNCTION: HashFunction = HashFunction::DoubleSipHash24;
+
+    fn hash(&self, key: &[u8], seed: u64) -> u64 {
+        self.low.hash(key, seed) ^ self.high.hash(key, seed ^ 1 << 63)
+    }
+}
+
+impl SecretKeyHasher for DoubleSipHash24 {
+    const KEY_LEN: usize = 32;
+
+    fn from_key_bytes(key: &[u8]) -> Option<Self> {
+        Some(Self::new(key.try_into().ok()?))
+    }
+
+    fn key_bytes(&self) -> Vec<u8> {
+        [self.low.key_bytes(), self.high.key_bytes()].concat()
+    }
+}
+
+/// Reference MurmurHash3_x64_128 (Appleby), returns `(h1, h2)`.
+fn murmur3_x64_128(key: &[u8], seed: u32) -> (u64, u64) {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+    let mix_k1 = |k1: u64| k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k2: u64| k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+
+    let mut h1 = seed as u64;
+    let mut h2 = seed as u64;
+
+    let mut blocks = key.chunks_exact(16);
+    for block in &mut blocks {
+        let (k1, k2) = block.split_at(8);
+        h1 ^= mix_k1(u64::from_le_bytes(k1.try_into().expect("8 bytes")));
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dc_e729);
+        h2 ^= mix_k2(u64::from_le_bytes(k2.try_into().expect("8 bytes")));
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5ab5);
+    }
+
+    let tail = blocks.remainder();
+    let (k1, k2) = tail.split_at(tail.len().min(8));
+    let little_endian = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |word, &byte| word << 8 | byte as u64)
+    };
+    if !k2.is_empty() {
+        h2 ^= mix_k2(little_endian(k2));
+    }
+    if !k1.is_empty() {
+        h1 ^= mix_k1(little_endian(k1));
+    }
+
+    h1 ^= key.len() as u64;
+    h2 ^= key.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+
+    (h1, h2)
+}
+
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^ (k >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seahash_matches_previous_hashing() {
+        assert_eq!(
+            SeaHash.hash(b"mango", 3),
+            seahash::hash_seeded(b"mango", 3, 0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_fnv1a_reference_vectors() {
+        assert_eq!(Fnv1a.hash(b"", 0), Fnv1a::OFFSET_BASIS);
+        assert_eq!(Fnv1a.hash(b"a", 0), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(Fnv1a.hash(b"foobar", 0), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_murmur3_reference_vectors() {
+        assert_eq!(murmur3_x64_128(b"", 0), (0, 0));
+        assert_eq!(
+            murmur3_x64_128(b"foo", 0),
+            (0xe271_8657_01f5_4561, 0x7eaf_87e4_2bba_7d87)
+        );
+        assert_eq!(
+            murmur3_x64_128(b"The quick brown fox jumps over the lazy dog", 0),
+            (0xe34b_bc7b_bc07_1b6c, 0x7a43_3ca9_c49a_9347)
+        );
+        // 16 byte block plus a 1 byte tail
+        assert_eq!(
+            murmur3_x64_128(b"0123456789abcdefX", 0).0,
+            0xcdeb_d2ac_b570_d6f7
+        );
+        assert_eq!(Murmur3.hash(b"hello", 42), 0xc4b8_b3c9_60af_6f08);
+    }
+
+    #[test]
+    fn test_siphash_depends_on_key() {
+        let a = SipHash24::new([1; 16]);
+        let b = SipHash24::new([2; 16]);
+
+        assert_eq!(a.hash(b"mango", 0), a.clone().hash(b"mango", 0));
+        assert_ne!(a.hash(b"mango", 0), b.hash(b"mango", 0));
+        assert!(!format!("{a:?}").contains('1'));
+    }
+
+    #[test]
+    fn test_seed_sensitive() {
+        assert_ne!(SeaHash.hash(b"mango", 0), SeaHash.hash(b"mango", 1));
+        assert_ne!(Fnv1a.hash(b"mango", 0), Fnv1a.hash(b"mango", 1));
+        assert_ne!(Murmur3.hash(b"mango", 0), Murmur3.hash(b"mango", 1));
+        let sip = SipHash24::new([7; 16]);
+        assert_ne!(sip.hash(b"mango", 0), sip.hash(b"mango", 1));
+        let double_sip = DoubleSipHash24::new([7; 32]);
+        assert_ne!(double_sip.hash(b"mango", 0), double_sip.hash(b"mango", 1));
+    }
+
+    #[test]
+    fn test_double_siphash_depends_on_both_halves() {
+        let key = *b"0123456789abcdef0123456789abcdef";
+        let mut other_high = key;
+        other_high[31] ^= 1;
+        let mut other_low = key;
+        other_low[0] ^= 1;
+
+        let hash = |key| DoubleSipHash24::new(key).hash(b"mango", 0);
+        assert_ne!(hash(key), hash(other_high));
+        assert_ne!(hash(key), hash(other_low));
+        assert!(!format!("{:?}", DoubleSipHash24::new(key)).contains('0'));
+    }
+
+    #[test]
+    fn test_double_siphash_equal_halves_dont_cancel() {
+        let double_sip = DoubleSipHash24::new([7; 32]);
+
+        assert_ne!(double_sip.hash(b"mango", 0), 0);
+        assert_ne!(double_sip.hash(b"mango", 0), double_sip.hash(b"apple", 0));
+    }
+
+    #[test]
+    fn test_key_bytes_round_trip() {
+        let key = *b"0123456789abcdef";
+        let sip = SipHash24::new(key);
+        assert_eq!(sip.key_bytes(), key);
+        assert_eq!(SipHash24::from_key_bytes(&sip.key_bytes()), Some(sip));
+        assert_eq!(SipHash24::from_key_bytes(&key[1..]), None);
+
+        let double_sip = DoubleSipHash24::new([9; 32]);
+        assert_eq!(double_sip.key_bytes(), [9; 32]);
+        assert_eq!(
+            DoubleSipHash24::from_key_bytes(&double_sip.key_bytes()),
+            Some(double_sip)
+        );
+    }
+}
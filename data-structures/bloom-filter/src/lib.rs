@@ -0,0 +1,8 @@
+pub mod bloom_filter;
+pub mod bloom_filters;
+pub mod false_positive_rate;
+pub mod hashing;
+pub mod rng;
+pub mod serialization;
+pub mod server;
+pub mod sketches;
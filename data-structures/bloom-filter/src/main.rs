@@ -1,37 +1,424 @@
-use std::vec;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
 use prettytable::{Row, Table};
 
-pub mod bloom_filter;
-pub mod bloom_filters;
+use bloom_filter::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use bloom_filter::bloom_filters::bloom_filter_prod::{BloomFilterProd, IncompatibleFilters};
+use bloom_filter::serialization::{HashAlgorithm, SerializationError};
 
-use bloom_filter::BloomFilter;
-//use bloom_filters::bloom_filter_32_arr::BloomFilter32;
+const USAGE: &str = "\
+usage:
+  bloom-filter build [--fpr P] [--elements N] [--hash seeded|double] -o FILTER [KEYS]
+  bloom-filter query FILTER [KEY...]
+  bloom-filter stats FILTER
+  bloom-filter merge -o FILTER FILTER...
 
-fn main() {
-    let mut bl = bloom_filters::bloom_filter_prod::BloomFilterProd::new(10, 0.01);
-    //let mut bl = BloomFilter32::default();
+Keys are newline delimited; KEYS and query keys default to stdin.
+build sizes the filter for N keys, by default as many as it is given. Only filters built
+with the same N, P and hash can be merged.
 
-    bloomy(&mut bl);
+exit codes:
+  0  success, for query every key is possibly present
+  1  query: at least one key is definitely absent
+  2  bad arguments
+  3  a filter or key file could not be read or written
+  4  merge: the filters were built with different parameters";
 
-    println!("{:?}", bl);
+const DEFAULT_FALSE_PROBABILITY: f32 = 0.01;
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Build {
+        keys: Option<PathBuf>,
+        output: PathBuf,
+        elements: Option<usize>,
+        false_probability: f32,
+        hash_algorithm: HashAlgorithm,
+    },
+    Query {
+        filter: PathBuf,
+        keys: Vec<String>,
+    },
+    Stats {
+        filter: PathBuf,
+    },
+    Merge {
+        output: PathBuf,
+        filters: Vec<PathBuf>,
+    },
+    Help,
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Io(PathBuf, io::Error),
+    Filter(PathBuf, SerializationError),
+    Incompatible(PathBuf, IncompatibleFilters),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Usage(_) => 2,
+            Self::Io(..) | Self::Filter(..) => 3,
+            Self::Incompatible(..) => 4,
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Usage(reason) => write!(f, "{reason}\n\n{USAGE}"),
+            Self::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            Self::Filter(path, err) => write!(f, "{}: {err}", path.display()),
+            Self::Incompatible(path, err) => write!(f, "{}: {err}", path.display()),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let result = parse(std::env::args().skip(1)).and_then(run);
+
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            eprintln!("bloom-filter: {err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let usage = |reason: &str| CliError::Usage(reason.to_string());
+
+    let subcommand = args.next().ok_or_else(|| usage("missing command"))?;
+    if subcommand == "-h" || subcommand == "--help" {
+        return Ok(Command::Help);
+    }
+    let mut output = None;
+    let mut elements = None;
+    let mut false_probability = None;
+    let mut hash_algorithm = None;
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| usage(&format!("{flag} needs a value")))
+        };
+
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(value(&arg)?)),
+            "--fpr" => {
+                false_probability = Some(
+                    value(&arg)?
+                        .parse()
+                        .ok()
+                        .filter(|p| *p > 0.0 && *p < 1.0)
+                        .ok_or_else(|| usage("--fpr must be between 0 and 1"))?,
+                )
+            }
+            "--elements" => {
+                elements = Some(
+                    value(&arg)?
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| usage("--elements must be a positive integer"))?,
+                )
+            }
+            "--hash" => {
+                hash_algorithm = Some(match value(&arg)?.as_str() {
+                    "seeded" => HashAlgorithm::Seeded,
+                    "double" => HashAlgorithm::Double,
+                    other => return Err(usage(&format!("unknown hash algorithm {other}"))),
+                })
+            }
+            "-h" | "--help" => return Ok(Command::Help),
+            _ => positional.push(arg),
+        }
+    }
+
+    let only = |allowed: bool, flag: &str| {
+        if allowed {
+            Ok(())
+        } else {
+            Err(usage(&format!("{subcommand} does not take {flag}")))
+        }
+    };
+
+    if subcommand != "build" {
+        only(false_probability.is_none(), "--fpr")?;
+        only(elements.is_none(), "--elements")?;
+        only(hash_algorithm.is_none(), "--hash")?;
+    }
+
+    match subcommand.as_str() {
+        "build" => {
+            if positional.len() > 1 {
+                return Err(usage("build reads keys from at most one file"));
+            }
+            Ok(Command::Build {
+                keys: positional.pop().map(PathBuf::from),
+                output: output.ok_or_else(|| usage("build needs -o FILTER"))?,
+                elements,
+                false_probability: false_probability.unwrap_or(DEFAULT_FALSE_PROBABILITY),
+                hash_algorithm: hash_algorithm.unwrap_or(HashAlgorithm::Seeded),
+            })
+        }
+        "query" | "stats" => {
+            only(output.is_none(), "-o")?;
+            let mut positional = positional.into_iter();
+            let filter = positional
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| usage(&format!("{subcommand} needs a FILTER")))?;
+
+            if subcommand == "stats" {
+                only(positional.len() == 0, "keys")?;
+                return Ok(Command::Stats { filter });
+            }
+            Ok(Command::Query {
+                filter,
+                keys: positional.collect(),
+            })
+        }
+        "merge" => {
+            if positional.is_empty() {
+                return Err(usage("merge needs at least one FILTER"));
+            }
+            Ok(Command::Merge {
+                output: output.ok_or_else(|| usage("merge needs -o FILTER"))?,
+                filters: positional.into_iter().map(PathBuf::from).collect(),
+            })
+        }
+        other => Err(usage(&format!("unknown command {other}"))),
+    }
 }
 
-fn bloomy(bl: &mut impl BloomFilter) {
-    let keys = vec!["mango", "apple", "orange", "banana"];
+fn run(command: Command) -> Result<u8, CliError> {
+    match command {
+        Command::Build {
+            keys,
+            output,
+            elements,
+            false_probability,
+            hash_algorithm,
+        } => {
+            let keys = match keys {
+                Some(path) => {
+                    let file = File::open(&path).map_err(|err| CliError::Io(path.clone(), err))?;
+                    read_keys(BufReader::new(file)).map_err(|err| CliError::Io(path, err))?
+                }
+                None => read_keys(io::stdin().lock())
+                    .map_err(|err| CliError::Io(PathBuf::from("<stdin>"), err))?,
+            };
+
+            let mut bl = BloomFilterProd::builder()
+                .elements(elements.unwrap_or(keys.len()).max(1))
+                .false_probability(false_probability as f64)
+                .hash_algorithm(hash_algorithm)
+                .build()
+                .map_err(|err| CliError::Usage(err.to_string()))?;
+            keys.iter().for_each(|key| bl.insert(key));
+
+            write_filter(&bl, output)?;
+            Ok(0)
+        }
+        Command::Query { filter, mut keys } => {
+            let bl = read_filter(filter)?;
+            if keys.is_empty() {
+                keys = read_keys(io::stdin().lock())
+                    .map_err(|err| CliError::Io(PathBuf::from("<stdin>"), err))?;
+            }
+
+            let results = keys.iter().map(|key| bl.contains(key)).collect::<Vec<_>>();
+            let mut table = Table::new();
+            keys.iter().zip(&results).for_each(|(key, result)| {
+                table.add_row(Row::from(vec![key, result.to_string().as_str()]));
+            });
+            print_table(&table)?;
+
+            Ok(if results.iter().all(|&found| found) {
+                0
+            } else {
+                1
+            })
+        }
+        Command::Stats { filter } => {
+            let bl = read_filter(filter)?;
+
+            let mut table = Table::new();
+            let stats = [
+                ("bits", bl.bit_count().to_string()),
+                ("hash functions", bl.hash_count().to_string()),
+                ("hash algorithm", format!("{:?}", bl.hash_algorithm())),
+                ("bits set", bl.set_bits().to_string()),
+                ("fill ratio", format!("{:.4}", bl.fill_ratio())),
+                ("estimated keys", format!("{:.0}", bl.estimated_len())),
+                (
+                    "false positive rate",
+                    format!("{:.6}", bl.current_false_positive_rate()),
+                ),
+            ];
+            stats.iter().for_each(|(name, value)| {
+                table.add_row(Row::from(vec![*name, value.as_str()]));
+            });
+            print_table(&table)?;
+            Ok(0)
+        }
+        Command::Merge { output, filters } => {
+            let mut filters = filters.into_iter();
+            let first = filters.next().expect("parse checks for one filter");
+            let mut merged = read_filter(first)?;
+
+            for path in filters {
+                let bl = read_filter(path.clone())?;
+                merged
+                    .union_with(&bl)
+                    .map_err(|err| CliError::Incompatible(path, err))?;
+            }
+
+            write_filter(&merged, output)?;
+            Ok(0)
+        }
+        Command::Help => {
+            stdout_ok(writeln!(io::stdout(), "{USAGE}"))?;
+            Ok(0)
+        }
+    }
+}
+
+/// Same format as `Table::printstd`, without panicking when the reader goes away,
+/// e.g. `bloom-filter query ... | head`.
+fn print_table(table: &Table) -> Result<(), CliError> {
+    stdout_ok(table.print(&mut io::stdout()).map(|_| ()))
+}
+
+/// A closed pipe is the reader's choice, not an error.
+fn stdout_ok(result: io::Result<()>) -> Result<(), CliError> {
+    match result {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            Err(CliError::Io(PathBuf::from("<stdout>"), err))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// One key per line, blank lines are skipped. A trailing `\r` is dropped so files from
+/// Windows work too.
+fn read_keys(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let key = line.strip_suffix('\r').unwrap_or(&line);
+        if !key.is_empty() {
+            keys.push(key.to_string());
+        }
+    }
+    Ok(keys)
+}
+
+fn read_filter(path: PathBuf) -> Result<BloomFilterProd, CliError> {
+    let file = File::open(&path).map_err(|err| CliError::Io(path.clone(), err))?;
+    BloomFilterProd::read_from(BufReader::new(file)).map_err(|err| CliError::Filter(path, err))
+}
+
+fn write_filter(bl: &BloomFilterProd, path: PathBuf) -> Result<(), CliError> {
+    let file = File::create(&path).map_err(|err| CliError::Io(path.clone(), err))?;
+    bl.write_to(BufWriter::new(file))
+        .map_err(|err| CliError::Filter(path, err))
+}
 
-    keys.iter().for_each(|&key| bl.insert(key));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut test_keys = vec!["carrot", "radish", "vegetables", "onion"];
-    test_keys.extend(keys);
+    fn parse_args(args: &str) -> Result<Command, CliError> {
+        parse(args.split_whitespace().map(String::from))
+    }
 
-    let results = test_keys.iter().map(|&key| bl.contains(key));
+    #[test]
+    fn test_parse_build() {
+        assert_eq!(
+            parse_args("build --fpr 0.001 keys.txt -o out.bf").unwrap(),
+            Command::Build {
+                keys: Some(PathBuf::from("keys.txt")),
+                output: PathBuf::from("out.bf"),
+                elements: None,
+                false_probability: 0.001,
+                hash_algorithm: HashAlgorithm::Seeded,
+            }
+        );
+        assert!(matches!(
+            parse_args("build --hash double -o out.bf").unwrap(),
+            Command::Build {
+                keys: None,
+                hash_algorithm: HashAlgorithm::Double,
+                ..
+            }
+        ));
+    }
 
-    let mut table = Table::new();
+    #[test]
+    fn test_parse_query_merge_stats() {
+        assert_eq!(
+            parse_args("query f.bf mango apple").unwrap(),
+            Command::Query {
+                filter: PathBuf::from("f.bf"),
+                keys: vec!["mango".to_string(), "apple".to_string()],
+            }
+        );
+        assert_eq!(
+            parse_args("merge -o all.bf a.bf b.bf").unwrap(),
+            Command::Merge {
+                output: PathBuf::from("all.bf"),
+                filters: vec![PathBuf::from("a.bf"), PathBuf::from("b.bf")],
+            }
+        );
+        assert_eq!(parse_args("--help").unwrap(), Command::Help);
+        assert_eq!(parse_args("query -h").unwrap(), Command::Help);
+        assert_eq!(
+            parse_args("stats f.bf").unwrap(),
+            Command::Stats {
+                filter: PathBuf::from("f.bf")
+            }
+        );
+    }
 
-    test_keys.iter().zip(results).for_each(|(key, result)| {
-        table.add_row(Row::from(vec![key, result.to_string().as_str()]));
-    });
+    #[test]
+    fn test_usage_errors() {
+        for args in [
+            "",
+            "frobnicate",
+            "build keys.txt",
+            "build --fpr 2 -o out.bf",
+            "build --fpr",
+            "build --hash md5 -o out.bf",
+            "build --elements 0 -o out.bf",
+            "build --elements many -o out.bf",
+            "query",
+            "stats f.bf extra",
+            "merge a.bf",
+            "query -o out.bf f.bf",
+            "query --fpr 0.1 f.bf mango",
+            "stats --hash double f.bf",
+            "merge --fpr 0.1 -o all.bf a.bf",
+            "merge --elements 10 -o all.bf a.bf",
+        ] {
+            let err = parse_args(args).unwrap_err();
+            assert_eq!(err.exit_code(), 2, "{args}");
+        }
+    }
 
-    table.printstd();
+    #[test]
+    fn test_read_keys() {
+        let keys = read_keys("mango\r\napple\n\norange\n".as_bytes()).unwrap();
+        assert_eq!(keys, ["mango", "apple", "orange"]);
+    }
 }
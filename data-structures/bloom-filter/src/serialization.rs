@@ -0,0 +1,313 @@
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+
+use seahash::SeaHasher;
+
+/// Current version of the binary format, bumped on every incompatible layout change.
+pub const FORMAT_VERSION: u16 = 2;
+/// Version 1 headers have no hash function byte, every filter written with them used seahash.
+const SEAHASH_ONLY_VERSION: u16 = 1;
+/// Upper bound on a stored hash count, far past the optimum of any practical false positive
+/// rate, so a crafted header can't make every lookup loop or allocate per hash function.
+pub const MAX_HASH_COUNT: u32 = 64;
+
+/// Identifies how a filter derives its bit indices from the hash function, stored in the header
+/// next to the `HashFunction` so a filter is never queried with a different hash than it was
+/// built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum HashAlgorithm {
+    /// One hash per hash function, seeded with `0..hash_count`.
+    Seeded = 1,
+    /// Two hashes, seeded with 0 and 1, every index derived from them by enhanced double hashing
+    /// (Kirsch–Mitzenmacher, Dillinger–Manolios).
+    Double = 2,
+    /// One hash, remixed with the filter's seed into three fuse segment positions.
+    Fuse = 3,
+}
+
+impl HashAlgorithm {
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, SerializationError> {
+        match id {
+            1 => Ok(Self::Seeded),
+            2 => Ok(Self::Double),
+            3 => Ok(Self::Fuse),
+            _ => Err(SerializationError::UnknownHashAlgorithm(id)),
+        }
+    }
+}
+
+/// The function keys are hashed with, see `hashing::KeyHasher`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum HashFunction {
+    SeaHash = 1,
+    Fnv1a = 2,
+    Murmur3 = 3,
+    SipHash24 = 4,
+    DoubleSipHash24 = 5,
+}
+
+impl HashFunction {
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, SerializationError> {
+        match id {
+            1 => Ok(Self::SeaHash),
+            2 => Ok(Self::Fnv1a),
+            3 => Ok(Self::Murmur3),
+            4 => Ok(Self::SipHash24),
+            5 => Ok(Self::DoubleSipHash24),
+            _ => Err(SerializationError::UnknownHashFunction(id)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SerializationError {
+    Io(io::Error),
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u16),
+    UnknownHashAlgorithm(u8),
+    UnknownHashFunction(u8),
+    /// The filter was written with another hash function than the one it is being read with.
+    HashFunctionMismatch {
+        expected: HashFunction,
+        found: HashFunction,
+    },
+    /// The header parses but describes a filter that can't exist, e.g. zero bits.
+    InvalidParameters(&'static str),
+    ChecksumMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// A keyed filter is being read with another secret key than it was written with.
+    KeyMismatch,
+    /// The filter was written without its secret key, which must be provided to read it.
+    KeyNotIncluded,
+}
+
+impl std::fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "i/o error: {err}"),
+            Self::BadMagic(magic) => write!(f, "bad magic bytes {magic:?}"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            Self::UnknownHashAlgorithm(id) => write!(f, "unknown hash algorithm id {id}"),
+            Self::UnknownHashFunction(id) => write!(f, "unknown hash function id {id}"),
+            Self::HashFunctionMismatch { expected, found } => {
+                write!(f, "filter was built with {found:?}, not {expected:?}")
+            }
+            Self::InvalidParameters(reason) => write!(f, "invalid parameters: {reason}"),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {expected:#018x}, got {actual:#018x}"
+            ),
+            Self::KeyMismatch => write!(f, "filter was written with another secret key"),
+            Self::KeyNotIncluded => write!(f, "filter was written without its secret key"),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SerializationError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Fixed header in front of every serialized filter:
+/// magic (4) | version (u16) | hash algorithm (u8) | hash function (u8) | hash count (u32) |
+/// bit length (u64), all little endian. Version 1 lacks the hash function byte.
+/// The body follows and a seahash checksum of everything closes the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub magic: [u8; 4],
+    pub hash_algorithm: HashAlgorithm,
+    pub hash_function: HashFunction,
+    pub hash_count: u32,
+    pub bit_len: u64,
+}
+
+impl Header {
+    /// Writes the header, refusing parameters `read` would reject.
+    pub(crate) fn write(
+        &self,
+        writer: &mut ChecksumWriter<impl Write>,
+    ) -> Result<(), SerializationError> {
+        if self.hash_count > MAX_HASH_COUNT {
+            return Err(SerializationError::InvalidParameters(
+                "hash count is too large",
+            ));
+        }
+
+        writer.write_all(&self.magic)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&[self.hash_algorithm.id(), self.hash_function.id()])?;
+        writer.write_all(&self.hash_count.to_le_bytes())?;
+        writer.write_all(&self.bit_len.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Reads and validates a header, failing unless it starts with `magic`.
+    pub(crate) fn read(
+        reader: &mut ChecksumReader<impl Read>,
+        magic: [u8; 4],
+    ) -> Result<Self, SerializationError> {
+        let mut found = [0; 4];
+        reader.read_exact(&mut found)?;
+        if found != magic {
+            return Err(SerializationError::BadMagic(found));
+        }
+
+        let version = reader.read_u16()?;
+        if version != FORMAT_VERSION && version != SEAHASH_ONLY_VERSION {
+            return Err(SerializationError::UnsupportedVersion(version));
+        }
+
+        let hash_algorithm = HashAlgorithm::from_id(reader.read_u8()?)?;
+        let hash_function = match version {
+            SEAHASH_ONLY_VERSION => HashFunction::SeaHash,
+            _ => HashFunction::from_id(reader.read_u8()?)?,
+        };
+        let hash_count = reader.read_u32()?;
+        let bit_len = reader.read_u64()?;
+
+        if hash_count == 0 {
+            return Err(SerializationError::InvalidParameters("hash count is zero"));
+        }
+        if hash_count > MAX_HASH_COUNT {
+            return Err(SerializationError::InvalidParameters(
+                "hash count is too large",
+            ));
+        }
+        if bit_len == 0 {
+            return Err(SerializationError::InvalidParameters("bit length is zero"));
+        }
+        if usize::try_from(bit_len).is_err() {
+            return Err(SerializationError::InvalidParameters(
+                "bit length does not fit in memory",
+            ));
+        }
+
+        Ok(Self {
+            magic,
+            hash_algorithm,
+            hash_function,
+            hash_count,
+            bit_len,
+        })
+    }
+}
+
+/// Hashes everything written through it, `finish` appends the checksum.
+pub(crate) struct ChecksumWriter<W> {
+    inner: W,
+    hasher: SeaHasher,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: SeaHasher::new(),
+        }
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        let checksum = self.hasher.finish();
+        self.inner.write_all(&checksum.to_le_bytes())?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.write(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hashes everything read through it, `verify` checks the trailing checksum.
+pub(crate) struct ChecksumReader<R> {
+    inner: R,
+    hasher: SeaHasher,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: SeaHasher::new(),
+        }
+    }
+
+    pub(crate) fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub(crate) fn verify(mut self) -> Result<(), SerializationError> {
+        let actual = self.hasher.finish();
+        let mut buf = [0; 8];
+        self.inner.read_exact(&mut buf)?;
+        let expected = u64::from_le_bytes(buf);
+
+        if expected != actual {
+            return Err(SerializationError::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.write(&buf[..read]);
+        Ok(read)
+    }
+}
@@ -0,0 +1,121 @@
+//! A standalone stand-in for RedisBloom: `BF.ADD`, `BF.EXISTS`, `BF.MADD`, `BF.MEXISTS`,
+//! `BF.RESERVE` and `BF.INFO` over RESP2, each name backed by a `BloomFilterProd`.
+//!
+//! Unlike RedisBloom's default filters these don't grow: adding past the reserved capacity
+//! keeps working but raises the false positive rate.
+
+pub mod resp;
+pub mod store;
+
+mod snapshot;
+
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::serialization::SerializationError;
+
+use resp::Reply;
+use store::Store;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Where filters are restored from on start and snapshotted to, nowhere if `None`.
+    pub snapshot_path: Option<PathBuf>,
+    pub snapshot_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            snapshot_path: None,
+            snapshot_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    store: Arc<Store>,
+    snapshot_interval: Duration,
+}
+
+impl Server {
+    /// Binds `addr` and restores the last snapshot, if `config` names one that exists.
+    pub fn bind(addr: impl ToSocketAddrs, config: Config) -> Result<Self, SerializationError> {
+        let store = Store::open(config.snapshot_path)?;
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            store: Arc::new(store),
+            snapshot_interval: config.snapshot_interval,
+        })
+    }
+
+    /// The bound address, useful after binding port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves every connection on its own thread, forever. Snapshots are taken every
+    /// `snapshot_interval` and whenever a client sends `SAVE`.
+    pub fn run(self) {
+        if let Some(path) = self.store.snapshot_path().map(PathBuf::from) {
+            let store = Arc::clone(&self.store);
+            thread::spawn(move || loop {
+                thread::sleep(self.snapshot_interval);
+                if let Err(err) = store.save(&path) {
+                    eprintln!("bloom-server: snapshot to {} failed: {err}", path.display());
+                }
+            });
+        }
+
+        for stream in self.listener.incoming() {
+            // e.g. out of file descriptors, the next accept may well succeed
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("bloom-server: accept failed: {err}");
+                    continue;
+                }
+            };
+            let store = Arc::clone(&self.store);
+            thread::spawn(move || {
+                if let Err(err) = serve(stream, &store) {
+                    eprintln!("bloom-server: connection closed: {err}");
+                }
+            });
+        }
+    }
+}
+
+fn serve(stream: TcpStream, store: &Store) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    loop {
+        let args = match resp::read_request(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Reply::Error(format!("ERR Protocol error: {err}")).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(err) => return Err(err),
+        };
+        if args.is_empty() {
+            continue;
+        }
+
+        store.execute(&args).write_to(&mut writer)?;
+        if args[0].eq_ignore_ascii_case(b"QUIT") {
+            return writer.flush();
+        }
+        // pipelined requests are answered in one write
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::{self, BufRead, Read, Write};
+
+/// Longest bulk string accepted, Redis' default `proto-max-bulk-len`.
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+const MAX_ARGUMENTS: usize = 1024 * 1024;
+const MAX_LINE_LEN: u64 = 64 * 1024;
+
+/// A RESP2 reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Nil,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Self::Simple(status) => write!(writer, "+{status}\r\n"),
+            Self::Error(message) => write!(writer, "-{message}\r\n"),
+            Self::Integer(value) => write!(writer, ":{value}\r\n"),
+            Self::Bulk(bytes) => {
+                write!(writer, "${}\r\n", bytes.len())?;
+                writer.write_all(bytes)?;
+                writer.write_all(b"\r\n")
+            }
+            Self::Nil => writer.write_all(b"$-1\r\n"),
+            Self::Array(replies) => {
+                write!(writer, "*{}\r\n", replies.len())?;
+                replies.iter().try_for_each(|reply| reply.write_to(writer))
+            }
+        }
+    }
+}
+
+/// Reads one request, an array of bulk strings or an inline command like `PING`.
+/// `Ok(None)` at the end of the stream.
+///
+/// Malformed input is an `InvalidData` error; the stream can't be resynchronised after it.
+pub fn read_request(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+
+    let Some(count) = line.strip_prefix(b"*") else {
+        let inline = line
+            .split(|byte| byte.is_ascii_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+        return Ok(Some(inline));
+    };
+
+    let count = parse_len(count, MAX_ARGUMENTS)?;
+    let mut args = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        let header = read_line(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        let len = header
+            .strip_prefix(b"$")
+            .ok_or_else(|| invalid("expected '$'"))?;
+        let len = parse_len(len, MAX_BULK_LEN)?;
+
+        // grows as bytes arrive, so a huge announced length costs nothing up front
+        let mut arg = Vec::new();
+        reader.by_ref().take(len as u64 + 2).read_to_end(&mut arg)?;
+        if arg.len() < len + 2 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if !arg.ends_with(b"\r\n") {
+            return Err(invalid("bulk string not terminated by CRLF"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+/// A line without its `\r\n`, `None` if the stream ends before one starts.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return match line.len() as u64 {
+            MAX_LINE_LEN => Err(invalid("line too long")),
+            _ => Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_len(digits: &[u8], max: usize) -> io::Result<usize> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|&len| len <= max)
+        .ok_or_else(|| invalid("invalid length"))
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &[u8]) -> io::Result<Option<Vec<Vec<u8>>>> {
+        read_request(&mut &input[..])
+    }
+
+    #[test]
+    fn test_read_array() {
+        let args = read(b"*2\r\n$6\r\nBF.ADD\r\n$5\r\nmango\r\n").unwrap();
+        assert_eq!(args, Some(vec![b"BF.ADD".to_vec(), b"mango".to_vec()]));
+    }
+
+    #[test]
+    fn test_read_binary_safe_bulk() {
+        let args = read(b"*1\r\n$4\r\na\r\nb\r\n").unwrap();
+        assert_eq!(args, Some(vec![b"a\r\nb".to_vec()]));
+    }
+
+    #[test]
+    fn test_read_inline() {
+        let args = read(b"BF.EXISTS  fruit mango\r\n").unwrap();
+        assert_eq!(
+            args,
+            Some(vec![
+                b"BF.EXISTS".to_vec(),
+                b"fruit".to_vec(),
+                b"mango".to_vec()
+            ])
+        );
+        assert_eq!(read(b"").unwrap(), None);
+    }
+
+    #[test]
+    fn test_reject_malformed() {
+        for input in [&b"*1\r\n:5\r\n"[..], b"*x\r\n", b"*1\r\n$3\r\nabcd\r\n"] {
+            let err = read(input).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{input:?}");
+        }
+        assert_eq!(
+            read(b"*1\r\n$10\r\nabc").unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_write_replies() {
+        let mut out = Vec::new();
+        Reply::Array(vec![
+            Reply::Simple("OK"),
+            Reply::Integer(1),
+            Reply::Bulk(b"Capacity".to_vec()),
+            Reply::Nil,
+            Reply::Error("ERR not found".to_string()),
+        ])
+        .write_to(&mut out)
+        .unwrap();
+
+        assert_eq!(
+            out,
+            b"*5\r\n+OK\r\n:1\r\n$8\r\nCapacity\r\n$-1\r\n-ERR not found\r\n"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::serialization::{ChecksumReader, ChecksumWriter, SerializationError};
+
+use super::store::Entry;
+
+const MAGIC: [u8; 4] = *b"BFSN";
+const VERSION: u16 = 1;
+
+/// magic (4) | version (u16) | entry count (u32), then per entry
+/// name length (u32) | name | capacity (u64) | error rate (f32 bits, u32) | items (u64) |
+/// the filter as `BloomFilterProd::write_to` writes it. A seahash checksum closes the stream.
+pub(crate) fn write(
+    filters: &HashMap<Vec<u8>, Entry>,
+    writer: impl Write,
+) -> Result<(), SerializationError> {
+    let mut writer = ChecksumWriter::new(writer);
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(filters.len() as u32).to_le_bytes())?;
+
+    for (name, entry) in filters {
+        writer.write_all(&(name.len() as u32).to_le_bytes())?;
+        writer.write_all(name)?;
+        writer.write_u64(entry.capacity)?;
+        writer.write_all(&entry.error_rate.to_bits().to_le_bytes())?;
+        writer.write_u64(entry.items)?;
+        entry.filter.write_to(&mut writer)?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+pub(crate) fn read(reader: impl Read) -> Result<HashMap<Vec<u8>, Entry>, SerializationError> {
+    let mut reader = ChecksumReader::new(reader);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(SerializationError::BadMagic(magic));
+    }
+    let version = reader.read_u16()?;
+    if version != VERSION {
+        return Err(SerializationError::UnsupportedVersion(version));
+    }
+
+    let mut filters = HashMap::new();
+    for _ in 0..reader.read_u32()? {
+        let name_len = reader.read_u32()? as u64;
+        let mut name = Vec::new();
+        (&mut reader).take(name_len).read_to_end(&mut name)?;
+        if name.len() as u64 != name_len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let entry = Entry {
+            capacity: reader.read_u64()?,
+            error_rate: f32::from_bits(reader.read_u32()?),
+            items: reader.read_u64()?,
+            filter: BloomFilterProd::read_from(&mut reader)?,
+        };
+        filters.insert(name, entry);
+    }
+
+    reader.verify()?;
+    Ok(filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+
+    fn entry(keys: &[&str]) -> Entry {
+        let mut filter = BloomFilterProd::new(100, 0.01);
+        keys.iter().for_each(|key| filter.insert(key));
+        Entry {
+            filter,
+            capacity: 100,
+            error_rate: 0.01,
+            items: keys.len() as u64,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let filters = HashMap::from([
+            (b"fruit".to_vec(), entry(&["mango", "apple"])),
+            (b"\0binary name".to_vec(), entry(&[])),
+        ]);
+        let mut bytes = Vec::new();
+        write(&filters, &mut bytes).unwrap();
+
+        let read_back = read(&bytes[..]).unwrap();
+        assert_eq!(read_back.len(), 2);
+        let fruit = &read_back[&b"fruit"[..]];
+        assert!(fruit.filter.contains("mango"));
+        assert_eq!(
+            (fruit.capacity, fruit.error_rate, fruit.items),
+            (100, 0.01, 2)
+        );
+    }
+
+    #[test]
+    fn test_reject_corrupted() {
+        let filters = HashMap::from([(b"fruit".to_vec(), entry(&["mango"]))]);
+        let mut bytes = Vec::new();
+        write(&filters, &mut bytes).unwrap();
+
+        bytes[12] ^= 1;
+        assert!(read(&bytes[..]).is_err());
+        assert!(matches!(
+            read(&b"BLMF\x01\x00"[..]),
+            Err(SerializationError::BadMagic(_))
+        ));
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::bloom_filter::{BloomFilter, ReadableBloomFilter};
+use crate::bloom_filters::bloom_filter_builder::BuildError;
+use crate::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use crate::serialization::SerializationError;
+
+use super::resp::Reply;
+use super::snapshot;
+
+/// What `BF.ADD` and `BF.MADD` create a missing filter with, RedisBloom's defaults.
+pub const DEFAULT_ERROR_RATE: f32 = 0.01;
+pub const DEFAULT_CAPACITY: u64 = 100;
+
+/// Largest filter `BF.RESERVE` allocates, 4 GiB of bits.
+const MAX_BITS: usize = 1 << 35;
+
+/// A named filter and the parameters it was reserved with.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    pub(crate) filter: BloomFilterProd,
+    pub(crate) capacity: u64,
+    pub(crate) error_rate: f32,
+    /// Adds that reported the item as new, RedisBloom's "Number of items inserted".
+    pub(crate) items: u64,
+}
+
+impl Entry {
+    fn reserve(capacity: u64, error_rate: f32) -> Self {
+        Self {
+            filter: BloomFilterProd::new(capacity as usize, error_rate),
+            capacity,
+            error_rate,
+            items: 0,
+        }
+    }
+
+    fn add(&mut self, item: &[u8]) -> bool {
+        if self.filter.contains(item) {
+            return false;
+        }
+        self.filter.insert(item);
+        self.items += 1;
+        true
+    }
+}
+
+/// The named filters shared by every connection.
+#[derive(Debug, Default)]
+pub struct Store {
+    filters: Mutex<HashMap<Vec<u8>, Entry>>,
+    snapshot_path: Option<PathBuf>,
+    /// Held for a whole `save`, the periodic snapshot and `SAVE` share one temporary file.
+    saving: Mutex<()>,
+}
+
+impl Store {
+    /// An empty store, or the one last snapshotted to `snapshot_path` if that file exists.
+    pub fn open(snapshot_path: Option<PathBuf>) -> Result<Self, SerializationError> {
+        let filters = match &snapshot_path {
+            Some(path) if path.exists() => snapshot::read(File::open(path)?)?,
+            _ => HashMap::new(),
+        };
+        Ok(Self {
+            filters: Mutex::new(filters),
+            snapshot_path,
+            saving: Mutex::new(()),
+        })
+    }
+
+    pub fn snapshot_path(&self) -> Option<&Path> {
+        self.snapshot_path.as_deref()
+    }
+
+    /// Writes every filter to `path` through a temporary file, so a crash mid-write leaves
+    /// the previous snapshot intact. The filters are only locked while serialized into memory,
+    /// not during the file I/O. Concurrent saves take turns.
+    pub fn save(&self, path: &Path) -> Result<(), SerializationError> {
+        let _saving = self.saving.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut snapshot = Vec::new();
+        snapshot::write(&self.lock(), &mut snapshot)?;
+
+        let mut file = File::create(&tmp)?;
+        file.write_all(&snapshot)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Runs one command, e.g. `["BF.ADD", "fruit", "mango"]`. Errors are replies too.
+    pub fn execute(&self, args: &[Vec<u8>]) -> Reply {
+        let Some((name, args)) = args.split_first() else {
+            return error("ERR empty command");
+        };
+        let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+
+        let arity_ok = match name.as_str() {
+            "bf.add" | "bf.exists" => args.len() == 2,
+            "bf.madd" | "bf.mexists" => args.len() >= 2,
+            "bf.reserve" => args.len() >= 3,
+            "bf.info" => matches!(args.len(), 1 | 2),
+            "ping" => args.len() <= 1,
+            "save" | "quit" | "command" => true,
+            _ => return error(&format!("ERR unknown command '{name}'")),
+        };
+        if !arity_ok {
+            return error(&format!(
+                "ERR wrong number of arguments for '{name}' command"
+            ));
+        }
+
+        match name.as_str() {
+            "bf.add" => {
+                Reply::Integer(self.with_entry(&args[0], |entry| entry.add(&args[1])) as i64)
+            }
+            "bf.madd" => Reply::Array(self.with_entry(&args[0], |entry| {
+                args[1..]
+                    .iter()
+                    .map(|item| Reply::Integer(entry.add(item) as i64))
+                    .collect()
+            })),
+            "bf.exists" => Reply::Integer(self.exists(&args[0], &args[1..])[0]),
+            "bf.mexists" => Reply::Array(
+                self.exists(&args[0], &args[1..])
+                    .into_iter()
+                    .map(Reply::Integer)
+                    .collect(),
+            ),
+            "bf.reserve" => self.reserve(args),
+            "bf.info" => self.info(&args[0], args.get(1)),
+            "ping" => match args.first() {
+                Some(message) => Reply::Bulk(message.clone()),
+                None => Reply::Simple("PONG"),
+            },
+            "save" => match &self.snapshot_path {
+                Some(path) => match self.save(path) {
+                    Ok(()) => Reply::Simple("OK"),
+                    Err(err) => error(&format!("ERR snapshot failed: {err}")),
+                },
+                None => error("ERR no snapshot file configured"),
+            },
+            // redis-cli asks for command docs on connect, an empty answer is fine
+            "command" => Reply::Array(Vec::new()),
+            "quit" => Reply::Simple("OK"),
+            _ => unreachable!("arity checked every known command"),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Vec<u8>, Entry>> {
+        // every update is a single insert into a filter, a panicking thread can't leave
+        // one half done
+        self.filters.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `f` on the named filter, created with the defaults if it doesn't exist yet.
+    fn with_entry<T>(&self, name: &[u8], f: impl FnOnce(&mut Entry) -> T) -> T {
+        let mut filters = self.lock();
+        let entry = filters
+            .entry(name.to_vec())
+            .or_insert_with(|| Entry::reserve(DEFAULT_CAPACITY, DEFAULT_ERROR_RATE));
+        f(entry)
+    }
+
+    /// 1 or 0 per item, all 0 if the filter doesn't exist.
+    fn exists(&self, name: &[u8], items: &[Vec<u8>]) -> Vec<i64> {
+        let filters = self.lock();
+        items
+            .iter()
+            .map(|item| {
+                filters
+                    .get(name)
+                    .is_some_and(|entry| entry.filter.contains(item)) as i64
+            })
+            .collect()
+    }
+
+    /// `BF.RESERVE name error_rate capacity [NONSCALING]`. Filters never scale, so
+    /// `NONSCALING` is accepted and `EXPANSION` refused.
+    fn reserve(&self, args: &[Vec<u8>]) -> Reply {
+        let error_rate = std::str::from_utf8(&args[1])
+            .ok()
+            .and_then(|rate| rate.parse::<f32>().ok())
+            .filter(|rate| *rate > 0.0 && *rate < 1.0);
+        let Some(error_rate) = error_rate else {
+            return error("ERR (0 < error rate range < 1)");
+        };
+        let capacity = std::str::from_utf8(&args[2])
+            .ok()
+            .and_then(|capacity| capacity.parse::<u64>().ok())
+            .filter(|capacity| *capacity > 0);
+        let Some(capacity) = capacity else {
+            return error("ERR (capacity should be larger than 0)");
+        };
+        for option in &args[3..] {
+            if !option.eq_ignore_ascii_case(b"NONSCALING") {
+                return error(&format!(
+                    "ERR unsupported option '{}', filters do not scale",
+                    String::from_utf8_lossy(option)
+                ));
+            }
+        }
+        let parameters = BloomFilterProd::builder()
+            .elements(capacity as usize)
+            .false_probability(error_rate as f64)
+            .parameters();
+        match parameters {
+            Ok(parameters) if parameters.bit_count <= MAX_BITS => {}
+            // more hash functions than a snapshot can hold, SAVE would succeed but the
+            // server could never load it again
+            Err(BuildError::HashCountTooLarge(_)) => return error("ERR error rate is too low"),
+            _ => return error("ERR filter would be too large"),
+        }
+
+        let mut filters = self.lock();
+        if filters.contains_key(&args[0]) {
+            return error("ERR item exists");
+        }
+        filters.insert(args[0].clone(), Entry::reserve(capacity, error_rate));
+        Reply::Simple("OK")
+    }
+
+    /// `BF.INFO name [CAPACITY|SIZE|FILTERS|ITEMS|EXPANSION]`, laid out like RedisBloom's.
+    fn info(&self, name: &[u8], field: Option<&Vec<u8>>) -> Reply {
+        let filters = self.lock();
+        let Some(entry) = filters.get(name) else {
+            return error("ERR not found");
+        };
+
+        let size = entry.filter.bit_count().div_ceil(8) as i64;
+        let fields = [
+            (
+                "CAPACITY",
+                "Capacity",
+                Reply::Integer(entry.capacity as i64),
+            ),
+            ("SIZE", "Size", Reply::Integer(size)),
+            ("FILTERS", "Number of filters", Reply::Integer(1)),
+            (
+                "ITEMS",
+                "Number of items inserted",
+                Reply::Integer(entry.items as i64),
+            ),
+            ("EXPANSION", "Expansion rate", Reply::Nil),
+        ];
+
+        match field {
+            Some(field) => fields
+                .into_iter()
+                .find(|(key, ..)| field.eq_ignore_ascii_case(key.as_bytes()))
+                .map(|(.., value)| Reply::Array(vec![value]))
+                .unwrap_or_else(|| error("ERR invalid information value")),
+            None => Reply::Array(
+                fields
+                    .into_iter()
+                    .flat_map(|(_, label, value)| [Reply::Bulk(label.into()), value])
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn error(message: &str) -> Reply {
+    Reply::Error(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(store: &Store, command: &str) -> Reply {
+        let args = command
+            .split_whitespace()
+            .map(|arg| arg.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        store.execute(&args)
+    }
+
+    #[test]
+    fn test_add_and_exists() {
+        let store = Store::default();
+
+        assert_eq!(run(&store, "BF.EXISTS fruit mango"), Reply::Integer(0));
+        assert_eq!(run(&store, "BF.ADD fruit mango"), Reply::Integer(1));
+        assert_eq!(run(&store, "bf.add fruit mango"), Reply::Integer(0));
+        assert_eq!(run(&store, "BF.EXISTS fruit mango"), Reply::Integer(1));
+        assert_eq!(
+            run(&store, "BF.MEXISTS fruit mango apple"),
+            Reply::Array(vec![Reply::Integer(1), Reply::Integer(0)])
+        );
+    }
+
+    #[test]
+    fn test_add_creates_default_filter() {
+        let store = Store::default();
+        run(&store, "BF.MADD fruit mango apple mango");
+
+        let entry = &store.lock()[&b"fruit"[..]];
+        assert_eq!(entry.capacity, DEFAULT_CAPACITY);
+        assert_eq!(entry.error_rate, DEFAULT_ERROR_RATE);
+        assert_eq!(entry.items, 2);
+    }
+
+    #[test]
+    fn test_reserve() {
+        let store = Store::default();
+
+        assert_eq!(
+            run(&store, "BF.RESERVE fruit 0.001 1000"),
+            Reply::Simple("OK")
+        );
+        assert_eq!(
+            run(&store, "BF.RESERVE fruit 0.001 1000"),
+            error("ERR item exists")
+        );
+        assert_eq!(
+            run(&store, "BF.RESERVE veg 0.001 1000 NONSCALING"),
+            Reply::Simple("OK")
+        );
+        for bad in [
+            "BF.RESERVE a 1.5 1000",
+            "BF.RESERVE a 0 1000",
+            "BF.RESERVE a 0.01 0",
+            "BF.RESERVE a 0.01 many",
+            "BF.RESERVE a 0.01 100 EXPANSION 2",
+            "BF.RESERVE a 0.000001 100000000000",
+        ] {
+            assert!(matches!(run(&store, bad), Reply::Error(_)), "{bad}");
+        }
+        assert!(!store.lock().contains_key(&b"a"[..]));
+
+        assert_eq!(
+            run(&store, "BF.RESERVE a 1e-25 100"),
+            error("ERR error rate is too low")
+        );
+    }
+
+    #[test]
+    fn test_info() {
+        let store = Store::default();
+        assert_eq!(run(&store, "BF.INFO fruit"), error("ERR not found"));
+
+        run(&store, "BF.RESERVE fruit 0.01 1000");
+        run(&store, "BF.ADD fruit mango");
+        let Reply::Array(info) = run(&store, "BF.INFO fruit") else {
+            panic!("BF.INFO replies with an array");
+        };
+        assert_eq!(info[0], Reply::Bulk(b"Capacity".to_vec()));
+        assert_eq!(info[1], Reply::Integer(1000));
+        assert_eq!(info[7], Reply::Integer(1));
+        assert_eq!(
+            run(&store, "BF.INFO fruit items"),
+            Reply::Array(vec![Reply::Integer(1)])
+        );
+    }
+
+    #[test]
+    fn test_command_errors() {
+        let store = Store::default();
+
+        assert_eq!(
+            run(&store, "BF.ADD fruit"),
+            error("ERR wrong number of arguments for 'bf.add' command")
+        );
+        assert_eq!(
+            run(&store, "FLUSHALL"),
+            error("ERR unknown command 'flushall'")
+        );
+        assert_eq!(
+            run(&store, "SAVE"),
+            error("ERR no snapshot file configured")
+        );
+        assert_eq!(run(&store, "PING"), Reply::Simple("PONG"));
+    }
+}
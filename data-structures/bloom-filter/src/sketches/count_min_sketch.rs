@@ -0,0 +1,292 @@
+use std::f64::consts::E;
+
+use crate::hashing::{KeyHasher, SeaHash};
+pub use crate::sketches::IncompatibleSketches;
+
+/// Approximate per-key counts in fixed memory (Cormode & Muthukrishnan).
+///
+/// `depth` rows of `width` counters; a key adds to one counter per row, picked by the hasher
+/// seeded with the row like `BloomFilterProd`'s seeded hashing, and its estimate is the
+/// smallest of them. Estimates never undercount. With probability `1 - delta` they
+/// overcount by at most `epsilon` times the total of all increments.
+#[derive(Debug, Clone)]
+pub struct CountMinSketch<H: KeyHasher = SeaHash> {
+    counters: Vec<u64>,
+    width: usize,
+    depth: usize,
+    total: u64,
+    conservative: bool,
+    hasher: H,
+}
+
+impl CountMinSketch {
+    /// `width = ⌈e / epsilon⌉` and `depth = ⌈ln(1 / delta)⌉`.
+    pub fn new(epsilon: f64, delta: f64) -> Self {
+        Self::with_hasher(epsilon, delta, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> CountMinSketch<H> {
+    pub fn with_hasher(epsilon: f64, delta: f64, hasher: H) -> Self {
+        assert!(
+            epsilon > 0.0 && epsilon < 1.0,
+            "epsilon must be between 0 and 1"
+        );
+        assert!(delta > 0.0 && delta < 1.0, "delta must be between 0 and 1");
+
+        let width = (E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil().max(1.0) as usize;
+
+        Self {
+            counters: vec![0; width * depth],
+            width,
+            depth,
+            total: 0,
+            conservative: false,
+            hasher,
+        }
+    }
+
+    /// Conservative update (Estan & Varghese): an increment only raises the counters that
+    /// would otherwise end up below the key's new estimate. Estimates are never worse and
+    /// usually much closer for skewed streams, but sketches must only ever be incremented.
+    pub fn with_conservative_update(mut self, conservative: bool) -> Self {
+        self.conservative = conservative;
+        self
+    }
+
+    pub fn increment<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K, by: u64) {
+        self.total = self.total.saturating_add(by);
+        let slots = self.slots(key.as_ref());
+
+        if self.conservative {
+            let target = self.estimate_slots(&slots).saturating_add(by);
+            for slot in slots {
+                self.counters[slot] = self.counters[slot].max(target);
+            }
+        } else {
+            for slot in slots {
+                self.counters[slot] = self.counters[slot].saturating_add(by);
+            }
+        }
+    }
+
+    /// At least the key's true count, see the type docs for how much more.
+    pub fn estimate<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> u64 {
+        self.estimate_slots(&self.slots(key.as_ref()))
+    }
+
+    /// Sum of every increment so far.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// How far an estimate may exceed the true count, with probability `1 - delta`.
+    pub fn error_bound(&self) -> f64 {
+        self.epsilon() * self.total as f64
+    }
+
+    /// What the width works out to, at most the `epsilon` asked for.
+    pub fn epsilon(&self) -> f64 {
+        E / self.width as f64
+    }
+
+    /// What the depth works out to, at most the `delta` asked for.
+    pub fn delta(&self) -> f64 {
+        (-(self.depth as f64)).exp()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn check_compatible(&self, other: &Self) -> Result<(), IncompatibleSketches> {
+        if self.width != other.width {
+            return Err(IncompatibleSketches::Width {
+                left: self.width,
+                right: other.width,
+            });
+        }
+        if self.depth != other.depth {
+            return Err(IncompatibleSketches::Depth {
+                left: self.depth,
+                right: other.depth,
+            });
+        }
+        if self.hasher != other.hasher {
+            return Err(IncompatibleSketches::Hasher);
+        }
+        Ok(())
+    }
+
+    /// Adds `other`'s counts, as if its increments had been made here. Conservative
+    /// sketches merge too: their estimates stay upper bounds, only less tight.
+    pub fn merge(&mut self, other: &Self) -> Result<(), IncompatibleSketches> {
+        self.check_compatible(other)?;
+
+        self.counters
+            .iter_mut()
+            .zip(&other.counters)
+            .for_each(|(counter, other)| *counter = counter.saturating_add(*other));
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+
+    /// One counter per row, rows laid out one after another.
+    fn slots(&self, key: &[u8]) -> Vec<usize> {
+        (0..self.depth)
+            .map(|row| {
+                row * self.width + (self.hasher.hash(key, row as u64) % self.width as u64) as usize
+            })
+            .collect()
+    }
+
+    fn estimate_slots(&self, slots: &[usize]) -> u64 {
+        slots
+            .iter()
+            .map(|&slot| self.counters[slot])
+            .min()
+            .expect("depth is at least 1")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::SipHash24;
+    use crate::rng::SplitMix64;
+
+    /// Zipf-like stream: key `i` of `keys` occurs about `keys / (i + 1)` times.
+    fn skewed_counts(keys: usize) -> Vec<(String, u64)> {
+        (0..keys)
+            .map(|i| (format!("key-{i}"), (keys / (i + 1)) as u64))
+            .collect()
+    }
+
+    fn fill(sketch: &mut CountMinSketch, counts: &[(String, u64)]) {
+        // one at a time and interleaved, like a real stream
+        let mut rng = SplitMix64::new(7);
+        let mut remaining = counts.to_vec();
+        while !remaining.is_empty() {
+            let i = (rng.next_u64() % remaining.len() as u64) as usize;
+            sketch.increment(&remaining[i].0, 1);
+            remaining[i].1 -= 1;
+            if remaining[i].1 == 0 {
+                remaining.swap_remove(i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sized_from_epsilon_and_delta() {
+        let sketch = CountMinSketch::new(0.001, 0.01);
+
+        assert_eq!(sketch.width(), 2719);
+        assert_eq!(sketch.depth(), 5);
+        assert!(sketch.epsilon() <= 0.001);
+        assert!(sketch.delta() <= 0.01);
+    }
+
+    #[test]
+    fn test_error_bound_holds() {
+        let counts = skewed_counts(5000);
+        for conservative in [false, true] {
+            let mut sketch =
+                CountMinSketch::new(0.001, 0.01).with_conservative_update(conservative);
+            fill(&mut sketch, &counts);
+
+            let bound = sketch.error_bound();
+            let mut beyond_bound = 0;
+            for (key, count) in &counts {
+                let estimate = sketch.estimate(key);
+                assert!(estimate >= *count, "{key}: {estimate} < {count}");
+                if (estimate - count) as f64 > bound {
+                    beyond_bound += 1;
+                }
+            }
+
+            // allowed: delta of the keys, i.e. 50
+            assert!(beyond_bound <= 50, "{beyond_bound} keys beyond {bound}");
+        }
+    }
+
+    #[test]
+    fn test_conservative_update_is_tighter() {
+        let counts = skewed_counts(5000);
+        let mut standard = CountMinSketch::new(0.01, 0.01);
+        let mut conservative = CountMinSketch::new(0.01, 0.01).with_conservative_update(true);
+        fill(&mut standard, &counts);
+        fill(&mut conservative, &counts);
+
+        let error = |sketch: &CountMinSketch| -> u64 {
+            counts
+                .iter()
+                .map(|(key, count)| sketch.estimate(key) - count)
+                .sum()
+        };
+        assert!(counts
+            .iter()
+            .all(|(key, _)| conservative.estimate(key) <= standard.estimate(key)));
+        assert!(error(&conservative) * 2 < error(&standard));
+    }
+
+    #[test]
+    fn test_increment_by() {
+        let mut sketch = CountMinSketch::new(0.01, 0.01);
+        sketch.increment("mango", 40);
+        sketch.increment("mango", 2);
+        sketch.increment("apple", 0);
+
+        assert_eq!(sketch.estimate("mango"), 42);
+        assert_eq!(sketch.estimate("apple"), 0);
+        assert_eq!(sketch.total(), 42);
+    }
+
+    #[test]
+    fn test_merge() {
+        let counts = skewed_counts(1000);
+        let (left, right) = counts.split_at(500);
+
+        let mut merged = CountMinSketch::new(0.01, 0.01);
+        let mut other = merged.clone();
+        let mut whole = merged.clone();
+        fill(&mut merged, left);
+        fill(&mut other, right);
+        fill(&mut whole, &counts);
+
+        merged.merge(&other).unwrap();
+        assert_eq!(merged.counters, whole.counters);
+        assert_eq!(merged.total(), whole.total());
+    }
+
+    #[test]
+    fn test_merge_incompatible() {
+        let mut sketch = CountMinSketch::new(0.01, 0.01);
+
+        assert_eq!(
+            sketch.merge(&CountMinSketch::new(0.001, 0.01)),
+            Err(IncompatibleSketches::Width {
+                left: 272,
+                right: 2719
+            })
+        );
+        assert_eq!(
+            sketch.merge(&CountMinSketch::new(0.01, 0.1)),
+            Err(IncompatibleSketches::Depth { left: 5, right: 3 })
+        );
+
+        let mut keyed = CountMinSketch::with_hasher(0.01, 0.01, SipHash24::new([1; 16]));
+        let other = CountMinSketch::with_hasher(0.01, 0.01, SipHash24::new([2; 16]));
+        assert_eq!(keyed.merge(&other), Err(IncompatibleSketches::Hasher));
+    }
+
+    #[test]
+    #[should_panic(expected = "epsilon must be between 0 and 1")]
+    fn test_invalid_epsilon() {
+        CountMinSketch::new(0.0, 0.01);
+    }
+}
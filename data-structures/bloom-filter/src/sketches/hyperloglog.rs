@@ -0,0 +1,614 @@
+use std::io::{Read, Write};
+
+use bitvec::prelude::*;
+
+use crate::hashing::{KeyHasher, SeaHash};
+use crate::serialization::{ChecksumReader, ChecksumWriter, HashFunction, SerializationError};
+use crate::sketches::IncompatibleSketches;
+
+const MAGIC: [u8; 4] = *b"HLLP";
+const VERSION: u16 = 1;
+const SPARSE: u8 = 1;
+const DENSE: u8 = 2;
+
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 18;
+/// Index bits of a sparse entry, HLL++'s p'.
+const SPARSE_PRECISION: u32 = 25;
+const RHO_BITS: u32 = 6;
+/// Dense registers are packed into this many bits when serialized, enough for `65 - p`.
+const REGISTER_BITS: usize = 6;
+
+/// Distinct count estimator (Flajolet et al.) with HLL++'s refinements (Heule et al.):
+/// 64-bit hashes, so no large range correction, and a sparse representation that keeps
+/// small sketches small and, at 25 index bits, very accurate.
+///
+/// Dense registers are estimated with Ertl's improved estimator ("New cardinality estimation
+/// algorithms for HyperLogLog sketches", 2017), which corrects the raw estimate's bias over
+/// the whole range without HLL++'s empirical bias tables, as Redis does.
+///
+/// The standard error is `1.04 / sqrt(2^precision)`, 0.81% at the default-ish precision 14
+/// which takes 16 KiB dense.
+#[derive(Debug, Clone)]
+pub struct HyperLogLog<H: KeyHasher = SeaHash> {
+    precision: u8,
+    registers: Registers,
+    hasher: H,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Registers {
+    /// Sorted by index, at most one entry per index: `index << RHO_BITS | rho` at
+    /// `SPARSE_PRECISION`.
+    Sparse(Vec<u32>),
+    /// One register per index, the longest run of leading zeros seen plus one.
+    Dense(Vec<u8>),
+}
+
+impl HyperLogLog {
+    /// `precision` index bits, between `MIN_PRECISION` and `MAX_PRECISION`.
+    pub fn new(precision: u8) -> Self {
+        Self::with_hasher(precision, SeaHash)
+    }
+
+    /// Reads a seahash sketch written by `write_to`, see `read_with_hasher`.
+    pub fn read_from(reader: impl Read) -> Result<Self, SerializationError> {
+        Self::read_with_hasher(reader, SeaHash)
+    }
+}
+
+impl<H: KeyHasher> HyperLogLog<H> {
+    pub fn with_hasher(precision: u8, hasher: H) -> Self {
+        assert!(
+            (MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+            "precision must be between {MIN_PRECISION} and {MAX_PRECISION}"
+        );
+
+        Self {
+            precision,
+            registers: Registers::Sparse(Vec::new()),
+            hasher,
+        }
+    }
+
+    pub fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        let hash = self.hasher.hash(key.as_ref(), 0);
+
+        match &mut self.registers {
+            Registers::Sparse(entries) => {
+                let entry = sparse_entry(hash);
+                match entries.binary_search_by_key(&(entry >> RHO_BITS), |e| e >> RHO_BITS) {
+                    Ok(i) => entries[i] = entries[i].max(entry),
+                    Err(i) => entries.insert(i, entry),
+                }
+                if entries.len() > self.sparse_limit() {
+                    self.densify();
+                }
+            }
+            Registers::Dense(registers) => {
+                let (index, rho) = dense_register(hash, self.precision);
+                registers[index] = registers[index].max(rho);
+            }
+        }
+    }
+
+    /// Estimated number of distinct keys inserted.
+    pub fn estimate(&self) -> f64 {
+        match &self.registers {
+            Registers::Sparse(entries) => {
+                // linear counting over 2^25 registers, exact-ish until far past the sparse limit
+                let m = (1u64 << SPARSE_PRECISION) as f64;
+                m * (m / (m - entries.len() as f64)).ln()
+            }
+            Registers::Dense(registers) => ertl_estimate(registers, self.precision),
+        }
+    }
+
+    /// Takes the register-wise maximum, the sketch of the union of both key sets.
+    pub fn merge(&mut self, other: &Self) -> Result<(), IncompatibleSketches> {
+        if self.precision != other.precision {
+            return Err(IncompatibleSketches::Precision {
+                left: self.precision,
+                right: other.precision,
+            });
+        }
+        if self.hasher != other.hasher {
+            return Err(IncompatibleSketches::Hasher);
+        }
+
+        match (&mut self.registers, &other.registers) {
+            (Registers::Sparse(entries), Registers::Sparse(others)) => {
+                *entries = merge_sparse(entries, others);
+                if entries.len() > self.sparse_limit() {
+                    self.densify();
+                }
+            }
+            (Registers::Sparse(_), Registers::Dense(_)) => {
+                self.densify();
+                return self.merge(other);
+            }
+            (Registers::Dense(registers), Registers::Sparse(others)) => {
+                for &entry in others {
+                    let (index, rho) = dense_from_sparse(entry, self.precision);
+                    registers[index] = registers[index].max(rho);
+                }
+            }
+            (Registers::Dense(registers), Registers::Dense(others)) => {
+                registers
+                    .iter_mut()
+                    .zip(others)
+                    .for_each(|(register, &other)| *register = (*register).max(other));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Standard error of `estimate` once dense, `1.04 / sqrt(m)`.
+    pub fn relative_error(&self) -> f64 {
+        1.04 / ((1u64 << self.precision) as f64).sqrt()
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.registers, Registers::Sparse(_))
+    }
+
+    /// Writes the sketch compactly: sparse entries as delta-encoded varints, dense registers
+    /// packed into 6 bits each.
+    ///
+    /// magic (4) | version (u16) | hash function (u8) | precision (u8) |
+    /// representation (u8, 1 sparse, 2 dense) | body | seahash checksum (u64).
+    /// A sparse body starts with its entry count (u32).
+    pub fn write_to(&self, writer: impl Write) -> Result<(), SerializationError> {
+        let mut writer = ChecksumWriter::new(writer);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[H::FUNCTION.id(), self.precision])?;
+
+        match &self.registers {
+            Registers::Sparse(entries) => {
+                writer.write_all(&[SPARSE])?;
+                writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+                let mut previous = 0;
+                for &entry in entries {
+                    write_varint(&mut writer, entry - previous)?;
+                    previous = entry;
+                }
+            }
+            Registers::Dense(registers) => {
+                writer.write_all(&[DENSE])?;
+                let mut packed = bitvec![u8, Lsb0; 0; registers.len() * REGISTER_BITS];
+                for (bits, &register) in packed.chunks_mut(REGISTER_BITS).zip(registers) {
+                    bits.store_le(register);
+                }
+                writer.write_all(packed.as_raw_slice())?;
+            }
+        }
+
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Reads a sketch written by `write_to`, rejecting corrupted input and sketches built
+    /// with another hash function than `hasher`'s.
+    pub fn read_with_hasher(reader: impl Read, hasher: H) -> Result<Self, SerializationError> {
+        let mut reader = ChecksumReader::new(reader);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SerializationError::BadMagic(magic));
+        }
+        let version = reader.read_u16()?;
+        if version != VERSION {
+            return Err(SerializationError::UnsupportedVersion(version));
+        }
+        let hash_function = HashFunction::from_id(reader.read_u8()?)?;
+        if hash_function != H::FUNCTION {
+            return Err(SerializationError::HashFunctionMismatch {
+                expected: H::FUNCTION,
+                found: hash_function,
+            });
+        }
+        let precision = reader.read_u8()?;
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return Err(SerializationError::InvalidParameters(
+                "precision out of range",
+            ));
+        }
+
+        let mut sketch = Self::with_hasher(precision, hasher);
+        match reader.read_u8()? {
+            SPARSE => {
+                let count = reader.read_u32()? as usize;
+                if count > sketch.sparse_limit() {
+                    return Err(SerializationError::InvalidParameters(
+                        "more sparse entries than a sparse sketch holds",
+                    ));
+                }
+                let mut entries = Vec::with_capacity(count);
+                let mut previous: Option<u32> = None;
+                for _ in 0..count {
+                    let delta = read_varint(&mut reader)?;
+                    let entry = previous
+                        .map_or(Some(delta), |previous| previous.checked_add(delta))
+                        .filter(|&entry| valid_sparse_entry(entry, previous))
+                        .ok_or(SerializationError::InvalidParameters(
+                            "sparse entries out of order or range",
+                        ))?;
+                    entries.push(entry);
+                    previous = Some(entry);
+                }
+                sketch.registers = Registers::Sparse(entries);
+            }
+            DENSE => {
+                let m = 1usize << precision;
+                let mut packed = vec![0u8; m * REGISTER_BITS / 8];
+                reader.read_exact(&mut packed)?;
+                let packed = BitVec::<u8, Lsb0>::from_vec(packed);
+                let registers = packed
+                    .chunks(REGISTER_BITS)
+                    .map(|bits| bits.load_le::<u8>())
+                    .collect::<Vec<_>>();
+                if registers.iter().any(|&rho| rho > max_rho(precision)) {
+                    return Err(SerializationError::InvalidParameters(
+                        "register larger than the hash allows",
+                    ));
+                }
+                sketch.registers = Registers::Dense(registers);
+            }
+            _ => {
+                return Err(SerializationError::InvalidParameters(
+                    "unknown representation",
+                ))
+            }
+        }
+
+        reader.verify()?;
+        Ok(sketch)
+    }
+
+    /// Sparse entries take 4 bytes, dense registers 6 bits; switch once sparse is larger.
+    fn sparse_limit(&self) -> usize {
+        (1usize << self.precision) * REGISTER_BITS / 32
+    }
+
+    fn densify(&mut self) {
+        if let Registers::Sparse(entries) = &self.registers {
+            let mut registers = vec![0; 1 << self.precision];
+            for &entry in entries {
+                let (index, rho) = dense_from_sparse(entry, self.precision);
+                registers[index] = registers[index].max(rho);
+            }
+            self.registers = Registers::Dense(registers);
+        }
+    }
+}
+
+/// Largest register value at `precision`: every hash bit past the index is zero.
+fn max_rho(precision: u8) -> u8 {
+    64 - precision + 1
+}
+
+/// `(index, rho)` of the top `precision` bits and the leading zeros after them.
+fn dense_register(hash: u64, precision: u8) -> (usize, u8) {
+    let index = (hash >> (64 - precision)) as usize;
+    let rest = hash << precision;
+    let rho = (rest.leading_zeros() as u8).min(64 - precision) + 1;
+    (index, rho)
+}
+
+fn sparse_entry(hash: u64) -> u32 {
+    let index = (hash >> (64 - SPARSE_PRECISION)) as u32;
+    let rest = hash << SPARSE_PRECISION;
+    let rho = rest.leading_zeros().min(64 - SPARSE_PRECISION) + 1;
+    index << RHO_BITS | rho
+}
+
+/// The dense register a sparse entry lands in. The sparse index holds `25 - p` more hash
+/// bits than the dense one, which count towards rho when they are all zero.
+fn dense_from_sparse(entry: u32, precision: u8) -> (usize, u8) {
+    let extra_bits = SPARSE_PRECISION - precision as u32;
+    let sparse_index = entry >> RHO_BITS;
+    let index = (sparse_index >> extra_bits) as usize;
+    let extra = sparse_index & ((1 << extra_bits) - 1);
+
+    let rho = match extra {
+        0 => extra_bits + (entry & ((1 << RHO_BITS) - 1)),
+        _ => extra.leading_zeros() - (32 - extra_bits) + 1,
+    };
+    (index, rho as u8)
+}
+
+fn valid_sparse_entry(entry: u32, previous: Option<u32>) -> bool {
+    let rho = entry & ((1 << RHO_BITS) - 1);
+    let index = entry >> RHO_BITS;
+    (1..=64 - SPARSE_PRECISION + 1).contains(&rho)
+        && index < 1 << SPARSE_PRECISION
+        && previous.is_none_or(|previous| previous >> RHO_BITS < index)
+}
+
+/// Union of two sorted entry lists, keeping the larger rho where both have an index.
+fn merge_sparse(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        let (l, r) = (left[i], right[j]);
+        match (l >> RHO_BITS).cmp(&(r >> RHO_BITS)) {
+            std::cmp::Ordering::Less => {
+                merged.push(l);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                merged.push(r);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                merged.push(l.max(r));
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    merged.extend_from_slice(&left[i..]);
+    merged.extend_from_slice(&right[j..]);
+    merged
+}
+
+/// Ertl's improved estimator over the register histogram, valid from 0 to far beyond 2^64
+/// distinct keys without switching to linear counting.
+fn ertl_estimate(registers: &[u8], precision: u8) -> f64 {
+    let q = (64 - precision) as usize;
+    let m = registers.len() as f64;
+    let mut histogram = vec![0u32; q + 2];
+    registers
+        .iter()
+        .for_each(|&rho| histogram[rho as usize] += 1);
+
+    let mut z = m * tau(1.0 - histogram[q + 1] as f64 / m);
+    for &count in histogram[1..=q].iter().rev() {
+        z += count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+
+    let alpha_infinity = 1.0 / (2.0 * std::f64::consts::LN_2);
+    alpha_infinity * m * m / z
+}
+
+/// `x + sum(x^(2^k) * 2^(k-1))` for k ≥ 1.
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+/// `(1 - x - sum((1 - x^(2^-k))^2 * 2^-k)) / 3` for k ≥ 1.
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+fn write_varint(writer: &mut impl Write, mut value: u32) -> std::io::Result<()> {
+    while value >= 0x80 {
+        writer.write_all(&[value as u8 | 0x80])?;
+        value >>= 7;
+    }
+    writer.write_all(&[value as u8])
+}
+
+fn read_varint(reader: &mut ChecksumReader<impl Read>) -> Result<u32, SerializationError> {
+    let mut value = 0u32;
+    for shift in (0..32).step_by(7) {
+        let byte = reader.read_u8()?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(SerializationError::InvalidParameters("varint too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::{Fnv1a, SipHash24};
+
+    fn filled(precision: u8, keys: std::ops::Range<u64>) -> HyperLogLog {
+        let mut hll = HyperLogLog::new(precision);
+        keys.for_each(|key| hll.insert(&key.to_le_bytes()));
+        hll
+    }
+
+    fn assert_close(hll: &HyperLogLog, actual: u64, sigmas: f64) {
+        let error = (hll.estimate() - actual as f64).abs() / actual.max(1) as f64;
+        assert!(
+            error <= sigmas * hll.relative_error(),
+            "estimated {} for {actual}, {error:.4} off",
+            hll.estimate()
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        let hll = HyperLogLog::new(14);
+        assert_eq!(hll.estimate(), 0.0);
+
+        let mut dense = hll.clone();
+        dense.densify();
+        assert_eq!(dense.estimate(), 0.0);
+    }
+
+    #[test]
+    fn test_small_counts_are_nearly_exact() {
+        for count in [1, 10, 100, 1000] {
+            let hll = filled(14, 0..count);
+            assert!(hll.is_sparse());
+            assert!(
+                (hll.estimate() - count as f64).abs() < 0.01 * count as f64 + 0.5,
+                "{} for {count}",
+                hll.estimate()
+            );
+        }
+    }
+
+    #[test]
+    fn test_accuracy_across_the_range() {
+        for count in [5_000, 20_000, 100_000, 1_000_000] {
+            let hll = filled(14, 0..count);
+            assert!(!hll.is_sparse());
+            assert_close(&hll, count, 3.0);
+        }
+        for precision in [MIN_PRECISION, 10, MAX_PRECISION] {
+            assert_close(&filled(precision, 0..200_000), 200_000, 3.0);
+        }
+    }
+
+    #[test]
+    fn test_duplicates_are_not_counted() {
+        let mut hll = filled(12, 0..10_000);
+        let once = hll.estimate();
+        (0..10_000u64).for_each(|key| hll.insert(&key.to_le_bytes()));
+        assert_eq!(hll.estimate(), once);
+    }
+
+    #[test]
+    fn test_sparse_converts_to_same_registers() {
+        for count in [100, 700, 20_000] {
+            let mut sparse = HyperLogLog::new(12);
+            let mut dense = HyperLogLog::new(12);
+            dense.densify();
+            for key in 0..count as u64 {
+                sparse.insert(&key.to_le_bytes());
+                dense.insert(&key.to_le_bytes());
+            }
+
+            sparse.densify();
+            assert_eq!(sparse.registers, dense.registers, "{count}");
+        }
+    }
+
+    #[test]
+    fn test_merge_equals_union() {
+        let union = filled(14, 0..300_000);
+        for (left, right) in [
+            (0..200_000, 100_000..300_000), // dense and dense
+            (0..300_000, 0..50),            // dense and sparse
+            (0..50, 0..300_000),            // sparse and dense
+        ] {
+            let mut merged = filled(14, left);
+            merged.merge(&filled(14, right)).unwrap();
+            assert_eq!(merged.registers, union.registers);
+        }
+
+        let mut sparse = filled(14, 0..100);
+        sparse.merge(&filled(14, 50..200)).unwrap();
+        assert!(sparse.is_sparse());
+        assert_eq!(sparse.registers, filled(14, 0..200).registers);
+    }
+
+    #[test]
+    fn test_merge_incompatible() {
+        let mut hll = HyperLogLog::new(14);
+        assert_eq!(
+            hll.merge(&HyperLogLog::new(12)),
+            Err(IncompatibleSketches::Precision {
+                left: 14,
+                right: 12
+            })
+        );
+
+        let mut keyed = HyperLogLog::with_hasher(14, SipHash24::new([1; 16]));
+        let other = HyperLogLog::with_hasher(14, SipHash24::new([2; 16]));
+        assert_eq!(keyed.merge(&other), Err(IncompatibleSketches::Hasher));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for count in [0, 3, 1000, 100_000] {
+            let hll = filled(14, 0..count);
+            let mut bytes = Vec::new();
+            hll.write_to(&mut bytes).unwrap();
+
+            let read_back = HyperLogLog::read_from(&bytes[..]).unwrap();
+            assert_eq!(read_back.registers, hll.registers, "{count}");
+            assert_eq!(read_back.estimate(), hll.estimate());
+        }
+    }
+
+    #[test]
+    fn test_serialized_size() {
+        let mut bytes = Vec::new();
+        filled(14, 0..100).write_to(&mut bytes).unwrap();
+        // at most 4 bytes per delta-encoded entry, against 12 KiB dense
+        assert!(bytes.len() <= 13 + 4 * 100 + 8, "{} bytes", bytes.len());
+
+        bytes.clear();
+        filled(14, 0..100_000).write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 9 + (1 << 14) * 6 / 8 + 8);
+    }
+
+    #[test]
+    fn test_reject_corrupted_or_mismatched() {
+        let mut bytes = Vec::new();
+        filled(10, 0..100_000).write_to(&mut bytes).unwrap();
+
+        let mut flipped = bytes.clone();
+        flipped[20] ^= 1;
+        assert!(matches!(
+            HyperLogLog::read_from(&flipped[..]),
+            Err(SerializationError::ChecksumMismatch { .. })
+        ));
+        assert!(HyperLogLog::read_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(matches!(
+            HyperLogLog::read_with_hasher(&bytes[..], Fnv1a),
+            Err(SerializationError::HashFunctionMismatch { .. })
+        ));
+
+        let mut out_of_range = bytes.clone();
+        out_of_range[7] = 19;
+        assert!(matches!(
+            HyperLogLog::read_from(&out_of_range[..]),
+            Err(SerializationError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_register_extraction() {
+        assert_eq!(dense_register(u64::MAX, 14), ((1 << 14) - 1, 1));
+        assert_eq!(dense_register(0, 14), (0, max_rho(14)));
+        assert_eq!(dense_from_sparse(sparse_entry(0), 14), (0, max_rho(14)));
+        assert_eq!(dense_from_sparse(sparse_entry(1 << 40), 14), (0, 10));
+    }
+
+    #[test]
+    #[should_panic(expected = "precision must be between 4 and 18")]
+    fn test_invalid_precision() {
+        HyperLogLog::new(3);
+    }
+}
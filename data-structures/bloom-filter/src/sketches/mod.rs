@@ -0,0 +1,36 @@
+pub mod count_min_sketch;
+pub mod hyperloglog;
+
+/// Returned by the merges when two sketches don't map keys to the same counters or registers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum IncompatibleSketches {
+    Width {
+        left: usize,
+        right: usize,
+    },
+    Depth {
+        left: usize,
+        right: usize,
+    },
+    Precision {
+        left: u8,
+        right: u8,
+    },
+    /// Same hash function, different key, e.g. two `SipHash24` sketches.
+    Hasher,
+}
+
+impl std::fmt::Display for IncompatibleSketches {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Width { left, right } => write!(f, "widths differ: {left} vs {right}"),
+            Self::Depth { left, right } => write!(f, "depths differ: {left} vs {right}"),
+            Self::Precision { left, right } => {
+                write!(f, "precisions differ: {left} vs {right}")
+            }
+            Self::Hasher => write!(f, "hashers are keyed differently"),
+        }
+    }
+}
+
+impl std::error::Error for IncompatibleSketches {}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn bloom_filter(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bloom-filter"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn path(dir: &Path, name: &str) -> String {
+    dir.join(name).to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_build_and_query() {
+    let dir = tempfile::tempdir().unwrap();
+    let keys = path(dir.path(), "keys.txt");
+    let filter = path(dir.path(), "fruit.bf");
+    fs::write(&keys, "mango\napple\norange\nbanana\n").unwrap();
+
+    let build = bloom_filter(&["build", "--fpr", "0.001", "-o", &filter, &keys], "");
+    assert_eq!(build.status.code(), Some(0));
+
+    let hit = bloom_filter(&["query", &filter, "mango", "apple"], "");
+    assert_eq!(hit.status.code(), Some(0));
+    let table = String::from_utf8(hit.stdout).unwrap();
+    assert!(table.contains("| mango | true |"), "{table}");
+
+    let miss = bloom_filter(&["query", &filter, "mango", "carrot"], "");
+    assert_eq!(miss.status.code(), Some(1));
+    assert!(String::from_utf8(miss.stdout)
+        .unwrap()
+        .contains("| carrot | false |"));
+}
+
+#[test]
+fn test_build_and_query_from_stdin() {
+    let dir = tempfile::tempdir().unwrap();
+    let filter = path(dir.path(), "fruit.bf");
+
+    let build = bloom_filter(&["build", "-o", &filter], "mango\napple\n");
+    assert_eq!(build.status.code(), Some(0));
+
+    let query = bloom_filter(&["query", &filter], "apple\nmango\n");
+    assert_eq!(query.status.code(), Some(0));
+}
+
+#[test]
+fn test_stats() {
+    let dir = tempfile::tempdir().unwrap();
+    let filter = path(dir.path(), "numbers.bf");
+    let keys = (0..1000).map(|i| format!("key-{i}\n")).collect::<String>();
+    bloom_filter(&["build", "-o", &filter], &keys);
+
+    let stats = bloom_filter(&["stats", &filter], "");
+    assert_eq!(stats.status.code(), Some(0));
+    let table = String::from_utf8(stats.stdout).unwrap();
+    assert!(table.contains("estimated keys"), "{table}");
+    assert!(table.contains("hash functions      | 7"), "{table}");
+}
+
+#[test]
+fn test_merge() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, merged) = (
+        path(dir.path(), "a.bf"),
+        path(dir.path(), "b.bf"),
+        path(dir.path(), "merged.bf"),
+    );
+    // sized for the same capacity, so different key counts still merge
+    bloom_filter(&["build", "--elements", "100", "-o", &a], "mango\napple\n");
+    bloom_filter(
+        &["build", "--elements", "100", "-o", &b],
+        "orange\nbanana\ncherry\n",
+    );
+
+    let merge = bloom_filter(&["merge", "-o", &merged, &a, &b], "");
+    assert_eq!(merge.status.code(), Some(0));
+    let query = bloom_filter(&["query", &merged, "mango", "banana", "cherry"], "");
+    assert_eq!(query.status.code(), Some(0));
+
+    let c = path(dir.path(), "c.bf");
+    bloom_filter(&["build", "-o", &c], "carrot\n");
+    let incompatible = bloom_filter(&["merge", "-o", &merged, &a, &c], "");
+    assert_eq!(incompatible.status.code(), Some(4));
+}
+
+#[test]
+fn test_error_exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let usage = bloom_filter(&["query"], "");
+    assert_eq!(usage.status.code(), Some(2));
+    assert!(String::from_utf8(usage.stderr).unwrap().contains("usage"));
+
+    // would need more hash functions than a filter file can hold
+    let too_precise = bloom_filter(
+        &["build", "--fpr", "1e-25", "-o", &path(dir.path(), "f.bf")],
+        "mango\n",
+    );
+    assert_eq!(too_precise.status.code(), Some(2));
+
+    let missing = bloom_filter(&["stats", &path(dir.path(), "missing.bf")], "");
+    assert_eq!(missing.status.code(), Some(3));
+
+    let garbage = path(dir.path(), "garbage.bf");
+    fs::write(&garbage, "not a filter").unwrap();
+    assert_eq!(
+        bloom_filter(&["stats", &garbage], "").status.code(),
+        Some(3)
+    );
+}
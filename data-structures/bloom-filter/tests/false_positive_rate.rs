@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use bloom_filter::bloom_filter::BloomFilter;
+use bloom_filter::bloom_filters::atomic_bloom_filter::AtomicBloomFilter;
+use bloom_filter::bloom_filters::blocked_bloom_filter::BlockedBloomFilter;
+use bloom_filter::bloom_filters::bloom_filter_32_arr::BloomFilter32;
+use bloom_filter::bloom_filters::bloom_filter_n::BloomFilterN;
+use bloom_filter::bloom_filters::bloom_filter_prod::BloomFilterProd;
+use bloom_filter::bloom_filters::counting_bloom_filter::CountingBloomFilter;
+use bloom_filter::bloom_filters::cuckoo_filter::CuckooFilter;
+use bloom_filter::bloom_filters::mmap_bloom_filter::MmapBloomFilterMut;
+use bloom_filter::bloom_filters::rotating_bloom_filter::RotatingBloomFilter;
+use bloom_filter::bloom_filters::scalable_bloom_filter::ScalableBloomFilter;
+use bloom_filter::false_positive_rate::{measure, FalsePositiveReport};
+use bloom_filter::serialization::HashAlgorithm;
+
+const ELEMENTS: usize = 5_000;
+const PROBES: usize = 50_000;
+const FALSE_PROBABILITY: f32 = 0.01;
+/// 50k probes at 1% measure the rate to within about 5%, the rest is slack for sizing.
+const TOLERANCE: f64 = 0.25;
+
+fn assert_within_target(name: &str, filter: &mut impl BloomFilter) {
+    let report = measure(filter, ELEMENTS, PROBES, 42);
+    assert!(
+        report.is_within(FALSE_PROBABILITY as f64, TOLERANCE),
+        "{name}: {report}"
+    );
+}
+
+#[test]
+fn test_prod() {
+    for hash_algorithm in [HashAlgorithm::Seeded, HashAlgorithm::Double] {
+        assert_within_target(
+            &format!("{hash_algorithm:?}"),
+            &mut BloomFilterProd::with_hash_algorithm(ELEMENTS, FALSE_PROBABILITY, hash_algorithm),
+        );
+    }
+}
+
+#[test]
+fn test_small_prod_filters_average_out() {
+    // a single 96-bit filter swings between 1% and 2%, so pool many of them
+    let report: FalsePositiveReport = (0..200)
+        .map(|seed| measure(&mut BloomFilterProd::new(10, 0.01), 10, 10_000, seed))
+        .sum();
+
+    assert!(report.is_within(0.01, TOLERANCE), "{report}");
+}
+
+#[test]
+fn test_fixed_size() {
+    // 48,000 bits, what the builder gives for 5,000 keys at 1% once rounded to words
+    assert_within_target("fixed size", &mut BloomFilterN::<750, 7>::new());
+}
+
+#[test]
+fn test_atomic() {
+    assert_within_target(
+        "atomic",
+        &mut AtomicBloomFilter::new(ELEMENTS, FALSE_PROBABILITY),
+    );
+}
+
+#[test]
+fn test_blocked() {
+    assert_within_target(
+        "blocked",
+        &mut BlockedBloomFilter::new(ELEMENTS, FALSE_PROBABILITY),
+    );
+}
+
+#[test]
+fn test_counting() {
+    assert_within_target(
+        "counting",
+        &mut CountingBloomFilter::new(ELEMENTS, FALSE_PROBABILITY),
+    );
+}
+
+#[test]
+fn test_cuckoo() {
+    assert_within_target(
+        "cuckoo",
+        &mut CuckooFilter::new(ELEMENTS, FALSE_PROBABILITY),
+    );
+}
+
+#[test]
+fn test_mmap() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_within_target(
+        "mmap",
+        &mut MmapBloomFilterMut::create(
+            dir.path().join("mmap.bfm"),
+            ELEMENTS,
+            FALSE_PROBABILITY,
+            HashAlgorithm::Double,
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn test_scalable_after_growing() {
+    assert_within_target(
+        "scalable",
+        &mut ScalableBloomFilter::new(ELEMENTS / 10, FALSE_PROBABILITY),
+    );
+}
+
+#[test]
+fn test_rotating_within_one_generation() {
+    // all keys land in the newest generation, the empty ones add nothing
+    assert_within_target(
+        "rotating",
+        &mut RotatingBloomFilter::new(ELEMENTS, FALSE_PROBABILITY, Duration::from_secs(3600), 4),
+    );
+}
+
+// The stable filter is left out: it forgets keys by design, so it can't pass the
+// no-false-negatives check.
+
+#[test]
+fn test_bloom_filter_32_quality() {
+    let rate = |elements| {
+        (0..50)
+            .map(|seed| measure(&mut BloomFilter32::default(), elements, 10_000, seed))
+            .sum::<FalsePositiveReport>()
+    };
+
+    for (elements, expected) in [(1, 0.03), (4, 0.12), (16, 0.5)] {
+        let report = rate(elements);
+        assert_eq!(report.false_negatives, 0);
+        assert!(
+            (report.rate() - expected).abs() < expected * 0.1,
+            "{elements} keys: {report}"
+        );
+    }
+}
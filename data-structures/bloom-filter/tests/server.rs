@@ -0,0 +1,324 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bloom_filter::server::{Config, Server};
+
+/// Replies as the client sees them, decoded independently of the server's own RESP code.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>),
+    Array(Vec<Value>),
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        Self {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    fn send(&mut self, args: &[&str]) {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+        }
+        self.writer.write_all(request.as_bytes()).unwrap();
+    }
+
+    fn call(&mut self, args: &[&str]) -> Value {
+        self.send(args);
+        self.receive()
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        let line = line.strip_suffix("\r\n").expect("CRLF terminated");
+        let (kind, rest) = line.split_at(1);
+
+        match kind {
+            "+" => Value::Simple(rest.to_string()),
+            "-" => Value::Error(rest.to_string()),
+            ":" => Value::Integer(rest.parse().unwrap()),
+            "$" => match rest.parse::<i64>().unwrap() {
+                -1 => Value::Bulk(None),
+                len => {
+                    let mut bulk = vec![0; len as usize + 2];
+                    self.reader.read_exact(&mut bulk).unwrap();
+                    bulk.truncate(len as usize);
+                    Value::Bulk(Some(String::from_utf8(bulk).unwrap()))
+                }
+            },
+            "*" => Value::Array(
+                (0..rest.parse::<usize>().unwrap())
+                    .map(|_| self.receive())
+                    .collect(),
+            ),
+            other => panic!("unexpected reply type {other}"),
+        }
+    }
+}
+
+fn start(config: Config) -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0", config).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    addr
+}
+
+fn integers(values: &[i64]) -> Value {
+    Value::Array(values.iter().copied().map(Value::Integer).collect())
+}
+
+fn with_snapshot(path: &Path, interval: Duration) -> Config {
+    Config {
+        snapshot_path: Some(path.to_path_buf()),
+        snapshot_interval: interval,
+    }
+}
+
+#[test]
+fn test_add_and_exists() {
+    let mut client = Client::connect(start(Config::default()));
+
+    assert_eq!(
+        client.call(&["BF.ADD", "fruit", "mango"]),
+        Value::Integer(1)
+    );
+    assert_eq!(
+        client.call(&["BF.ADD", "fruit", "mango"]),
+        Value::Integer(0)
+    );
+    assert_eq!(
+        client.call(&["BF.EXISTS", "fruit", "mango"]),
+        Value::Integer(1)
+    );
+    assert_eq!(
+        client.call(&["bf.exists", "fruit", "carrot"]),
+        Value::Integer(0)
+    );
+    assert_eq!(
+        client.call(&["BF.EXISTS", "veg", "carrot"]),
+        Value::Integer(0)
+    );
+}
+
+#[test]
+fn test_madd_and_mexists() {
+    let mut client = Client::connect(start(Config::default()));
+
+    assert_eq!(
+        client.call(&["BF.MADD", "fruit", "mango", "apple", "mango"]),
+        integers(&[1, 1, 0])
+    );
+    assert_eq!(
+        client.call(&["BF.MEXISTS", "fruit", "apple", "carrot", "mango"]),
+        integers(&[1, 0, 1])
+    );
+}
+
+#[test]
+fn test_reserve_and_info() {
+    let mut client = Client::connect(start(Config::default()));
+
+    assert_eq!(
+        client.call(&["BF.RESERVE", "fruit", "0.001", "1000"]),
+        Value::Simple("OK".to_string())
+    );
+    assert_eq!(
+        client.call(&["BF.RESERVE", "fruit", "0.001", "1000"]),
+        Value::Error("ERR item exists".to_string())
+    );
+    client.call(&["BF.MADD", "fruit", "mango", "apple"]);
+
+    let Value::Array(info) = client.call(&["BF.INFO", "fruit"]) else {
+        panic!("BF.INFO replies with an array");
+    };
+    let labels = info
+        .iter()
+        .step_by(2)
+        .map(|label| match label {
+            Value::Bulk(Some(label)) => label.as_str(),
+            other => panic!("label {other:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        [
+            "Capacity",
+            "Size",
+            "Number of filters",
+            "Number of items inserted",
+            "Expansion rate"
+        ]
+    );
+    assert_eq!(info[1], Value::Integer(1000));
+    assert_eq!(info[7], Value::Integer(2));
+    assert_eq!(info[9], Value::Bulk(None));
+
+    assert_eq!(
+        client.call(&["BF.INFO", "veg"]),
+        Value::Error("ERR not found".to_string())
+    );
+}
+
+#[test]
+fn test_errors_keep_connection_open() {
+    let mut client = Client::connect(start(Config::default()));
+
+    assert_eq!(
+        client.call(&["BF.ADD", "fruit"]),
+        Value::Error("ERR wrong number of arguments for 'bf.add' command".to_string())
+    );
+    assert!(matches!(client.call(&["GET", "fruit"]), Value::Error(_)));
+    assert!(matches!(
+        client.call(&["BF.RESERVE", "fruit", "2", "100"]),
+        Value::Error(_)
+    ));
+    assert_eq!(client.call(&["PING"]), Value::Simple("PONG".to_string()));
+}
+
+#[test]
+fn test_pipelined_and_inline_commands() {
+    let mut client = Client::connect(start(Config::default()));
+
+    client.send(&["BF.ADD", "fruit", "mango"]);
+    client.send(&["BF.ADD", "fruit", "apple"]);
+    client
+        .writer
+        .write_all(b"BF.MEXISTS fruit mango apple carrot\r\n")
+        .unwrap();
+
+    assert_eq!(client.receive(), Value::Integer(1));
+    assert_eq!(client.receive(), Value::Integer(1));
+    assert_eq!(client.receive(), integers(&[1, 1, 0]));
+}
+
+#[test]
+fn test_concurrent_clients_share_filters() {
+    let addr = start(Config::default());
+
+    let writers = (0..4)
+        .map(|i| {
+            thread::spawn(move || {
+                let mut client = Client::connect(addr);
+                for j in 0..50 {
+                    client.call(&["BF.ADD", "numbers", &format!("{i}-{j}")]);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    writers
+        .into_iter()
+        .for_each(|writer| writer.join().unwrap());
+
+    let mut client = Client::connect(addr);
+    for i in 0..4 {
+        assert_eq!(
+            client.call(&["BF.EXISTS", "numbers", &format!("{i}-49")]),
+            Value::Integer(1)
+        );
+    }
+}
+
+#[test]
+fn test_protocol_error_closes_connection() {
+    let mut client = Client::connect(start(Config::default()));
+
+    client.writer.write_all(b"*1\r\n:5\r\n").unwrap();
+    assert!(matches!(client.receive(), Value::Error(message) if message.contains("Protocol")));
+    let mut rest = Vec::new();
+    assert_eq!(client.reader.read_to_end(&mut rest).unwrap(), 0);
+}
+
+#[test]
+fn test_save_and_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshot = dir.path().join("filters.snapshot");
+    let interval = Duration::from_secs(3600);
+
+    let mut client = Client::connect(start(with_snapshot(&snapshot, interval)));
+    client.call(&["BF.RESERVE", "fruit", "0.001", "500"]);
+    client.call(&["BF.MADD", "fruit", "mango", "apple"]);
+    assert_eq!(client.call(&["SAVE"]), Value::Simple("OK".to_string()));
+
+    let mut restored = Client::connect(start(with_snapshot(&snapshot, interval)));
+    assert_eq!(
+        restored.call(&["BF.MEXISTS", "fruit", "mango", "apple", "carrot"]),
+        integers(&[1, 1, 0])
+    );
+    assert_eq!(
+        restored.call(&["BF.INFO", "fruit", "CAPACITY"]),
+        integers(&[500])
+    );
+}
+
+#[test]
+fn test_periodic_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshot = dir.path().join("filters.snapshot");
+
+    let mut client = Client::connect(start(with_snapshot(&snapshot, Duration::from_millis(50))));
+    client.call(&["BF.ADD", "fruit", "mango"]);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if snapshot.exists() {
+            let mut restored =
+                Client::connect(start(with_snapshot(&snapshot, Duration::from_secs(3600))));
+            if restored.call(&["BF.EXISTS", "fruit", "mango"]) == Value::Integer(1) {
+                break;
+            }
+        }
+        assert!(
+            Instant::now() < deadline,
+            "no snapshot with the key was written"
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_binary_serves_on_printed_address() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bloom-server"))
+        .args(["--bind", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let addr = line
+        .trim()
+        .strip_prefix("listening on ")
+        .expect("address announced")
+        .parse()
+        .unwrap();
+
+    let mut client = Client::connect(addr);
+    assert_eq!(
+        client.call(&["BF.ADD", "fruit", "mango"]),
+        Value::Integer(1)
+    );
+    child.kill().unwrap();
+    child.wait().unwrap();
+}